@@ -0,0 +1,267 @@
+use crate::generate::solid_png;
+use crate::utils::{NewDatabase, decompress, open_db};
+use base64::Engine as _;
+use eyre::{Context, Result, eyre};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, params_from_iter};
+use serde_json::Value;
+use std::path::Path;
+
+/// Fields whose string values are structure, not content, and are copied as-is:
+/// format versions, enum tags, timestamps (which must stay parseable) and opaque
+/// random ids (which tie tool uses to their results).
+const KEEP: &[&str] = &[
+    "version",
+    "type",
+    "role",
+    "updated_at",
+    "timestamp",
+    "id",
+    "tool_use_id",
+    "parent_thread_id",
+];
+
+/// Fields whose string value may be a unit enum variant (`"Resume"`,
+/// `"PastedImage"`, `"NotGenerated"`), kept when it looks like one.
+const UNIT_VARIANTS: &[&str] = &["messages", "uri", "detailed_summary_state"];
+
+/// Columns of the `threads` table copied unchanged.
+const KEEP_COLUMNS: &[&str] = &["id", "parent_id", "updated_at", "data_type"];
+
+pub struct AnonymizeSummary {
+    pub threads: usize,
+    /// Rows that could not be decoded, and so could not be scrubbed.
+    pub skipped: usize,
+}
+
+/// Copy the `threads` table of `db_path` to a new database at `output`, replacing
+/// every piece of text, path, URL and image with a placeholder of the same shape.
+///
+/// The table is recreated with the source's own `CREATE TABLE` statement and every
+/// column is copied, so schema differences survive. JSON keys (and so enum variant
+/// names), numbers, booleans and the fields in [`KEEP`] are kept; strings keep their
+/// length, whitespace and punctuation, with letters and digits masked. Images become
+/// blank PNGs of the same dimensions. The result fails to deserialize exactly where
+/// the original does.
+pub fn anonymize(db_path: &Path, output: &Path, force: bool) -> Result<AnonymizeSummary> {
+    let db = NewDatabase::create(output, force)?;
+
+    let src = open_db(db_path)?;
+    let create_sql: String = src
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE type = 'table' AND name = 'threads'",
+            [],
+            |row| row.get(0),
+        )
+        .wrap_err_with(|| format!("No `threads` table in {}", db_path.display()))?;
+
+    let mut dst = Connection::open(db.path())
+        .wrap_err_with(|| format!("Failed to create: {}", output.display()))?;
+    dst.execute_batch(&create_sql)
+        .wrap_err("Failed to create threads table")?;
+
+    let mut stmt = src.prepare("SELECT * FROM threads")?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+    let data_type_col = columns.iter().position(|c| c == "data_type");
+    let insert = format!(
+        "INSERT INTO threads ({}) VALUES ({})",
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+
+    let mut summary = AnonymizeSummary {
+        threads: 0,
+        skipped: 0,
+    };
+    let tx = dst.transaction()?;
+    {
+        let mut insert = tx.prepare(&insert)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let values: Vec<SqlValue> = (0..columns.len())
+                .map(|i| row.get(i))
+                .collect::<Result<_, _>>()?;
+            let data_type = data_type_col.and_then(|i| match &values[i] {
+                SqlValue::Text(t) => Some(t.clone()),
+                _ => None,
+            });
+
+            let mut scrubbed = Vec::with_capacity(values.len());
+            let mut failed = None;
+            for (column, value) in columns.iter().zip(values) {
+                let value = if column == "data" {
+                    match anonymize_data(data_type.as_deref(), value) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            failed = Some(e);
+                            break;
+                        }
+                    }
+                } else if KEEP_COLUMNS.contains(&column.as_str()) {
+                    value
+                } else {
+                    anonymize_sql(value)
+                };
+                scrubbed.push(value);
+            }
+
+            if let Some(e) = failed {
+                let id = scrubbed.first().map(sql_text).unwrap_or_default();
+                eprintln!("Skipped {}: {:#}", id, e);
+                summary.skipped += 1;
+                continue;
+            }
+            insert.execute(params_from_iter(scrubbed))?;
+            summary.threads += 1;
+        }
+    }
+    tx.commit()
+        .wrap_err("Failed to commit anonymized threads")?;
+    drop(dst);
+    db.persist()?;
+
+    Ok(summary)
+}
+
+fn sql_text(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Text(t) => t.clone(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// Scrub the thread JSON in the `data` column, keeping its `data_type` encoding.
+fn anonymize_data(data_type: Option<&str>, value: SqlValue) -> Result<SqlValue> {
+    let data_type = data_type.ok_or_else(|| eyre!("Row has no data_type"))?;
+    let raw = match value {
+        SqlValue::Blob(b) => b,
+        SqlValue::Text(t) => t.into_bytes(),
+        other => return Err(eyre!("Unexpected data column value: {:?}", other)),
+    };
+    let json = decompress(data_type, &raw)?;
+    let mut thread: Value = serde_json::from_slice(&json).wrap_err("Thread data is not JSON")?;
+    anonymize_value(&mut thread, None);
+    let json = serde_json::to_vec(&thread)?;
+    let data = match data_type {
+        "zstd" => zstd::encode_all(json.as_slice(), 3)?,
+        _ => json,
+    };
+    Ok(SqlValue::Blob(data))
+}
+
+/// Scrub a column the exporter does not know, such as one added by a newer Zed.
+fn anonymize_sql(value: SqlValue) -> SqlValue {
+    match value {
+        SqlValue::Text(t) => SqlValue::Text(mask(&t)),
+        SqlValue::Blob(b) => SqlValue::Blob(vec![0; b.len()]),
+        other => other,
+    }
+}
+
+fn anonymize_value(value: &mut Value, key: Option<&str>) {
+    match value {
+        Value::String(s) => {
+            if let Some(replacement) = anonymize_string(key, s) {
+                *s = replacement;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                anonymize_value(item, key);
+            }
+        }
+        Value::Object(map) => {
+            // Only an image's `source` is base64 data; elsewhere it is text.
+            let is_image = key.is_some_and(|k| k.eq_ignore_ascii_case("image"))
+                || map.keys().any(|k| k.eq_ignore_ascii_case("size"));
+            let size = image_size(map);
+            for (k, v) in map.iter_mut() {
+                if is_image
+                    && k.eq_ignore_ascii_case("source")
+                    && let Value::String(source) = v
+                {
+                    *source = placeholder_image(source, size);
+                } else {
+                    anonymize_value(v, Some(k));
+                }
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+}
+
+/// The masked form of `s` found under `key`, or `None` to keep it.
+fn anonymize_string(key: Option<&str>, s: &str) -> Option<String> {
+    let key = key.unwrap_or_default();
+    if KEEP.contains(&key) || (UNIT_VARIANTS.contains(&key) && is_identifier(s)) {
+        return None;
+    }
+    // Keep the scheme so the value still parses as a URL.
+    if key == "url"
+        && let Some((scheme, rest)) = s.split_once("://")
+    {
+        return Some(format!("{}://{}", scheme, mask(rest)));
+    }
+    Some(mask(s))
+}
+
+/// `PascalCase` with no separators, like a serialized unit variant.
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_uppercase()) && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Letters become `x`/`X` and digits `0`; whitespace and ASCII punctuation are kept,
+/// so line structure, paths and URLs keep their shape. Everything else becomes `x`.
+fn mask(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_digit() {
+                '0'
+            } else if c.is_uppercase() {
+                'X'
+            } else if c.is_whitespace() || c.is_ascii_punctuation() {
+                c
+            } else {
+                'x'
+            }
+        })
+        .collect()
+}
+
+/// Width and height from an image's `size` field, matched case-insensitively like
+/// the importer does.
+fn image_size(map: &serde_json::Map<String, Value>) -> Option<(u32, u32)> {
+    let size = map
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("size"))?
+        .1
+        .as_object()?;
+    let dim = |name: &str| {
+        size.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .and_then(|(_, v)| v.as_u64())
+            .map(|v| v as u32)
+    };
+    Some((dim("width")?, dim("height")?))
+}
+
+/// A blank PNG standing in for the base64 image `source`, of the same dimensions
+/// when they are known from `size` or a PNG header.
+fn placeholder_image(source: &str, size: Option<(u32, u32)>) -> String {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let (width, height) = size
+        .or_else(|| png_size(&engine.decode(source).ok()?))
+        .unwrap_or((1, 1));
+    // Keep absurd sizes from a corrupt header from allocating gigabytes.
+    let (width, height) = (width.clamp(1, 4096), height.clamp(1, 4096));
+    engine.encode(solid_png(width, height, [0x80, 0x80, 0x80]))
+}
+
+fn png_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"\x89PNG") || bytes.len() < 24 {
+        return None;
+    }
+    let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+    let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+    Some((width, height))
+}
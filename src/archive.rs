@@ -0,0 +1,169 @@
+use crate::utils::{remove_stale_temp_files, write_atomic};
+use clap::ValueEnum;
+use eyre::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How raw thread JSON is stored under `target_dir/raw/`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    /// `raw/<id>.json.zst`, zstd level 19.
+    Zstd,
+    /// `raw/<id>.json`, uncompressed.
+    Json,
+}
+
+impl RawFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RawFormat::Zstd => "json.zst",
+            RawFormat::Json => "json",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            RawFormat::Zstd => RawFormat::Json,
+            RawFormat::Json => RawFormat::Zstd,
+        }
+    }
+}
+
+/// Lossless store of the decompressed thread JSON, keyed by thread id and content hash.
+///
+/// `raw/index.json` maps each id to the SHA-256 of the JSON last written, so unchanged
+/// threads are not rewritten. A thread's file is only ever replaced by a newer copy,
+/// never deleted, so a thread removed from Zed stays recoverable.
+pub struct RawArchive {
+    dir: PathBuf,
+    format: RawFormat,
+    index: Mutex<BTreeMap<String, String>>,
+}
+
+impl RawArchive {
+    pub const DIR: &'static str = "raw";
+    const INDEX: &'static str = "index.json";
+
+    pub fn open(target_dir: &Path, format: RawFormat) -> Result<Self> {
+        let dir = target_dir.join(Self::DIR);
+        fs::create_dir_all(&dir).wrap_err("Failed to create raw archive dir")?;
+        remove_stale_temp_files(&dir);
+        let index = fs::read(dir.join(Self::INDEX))
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default();
+        Ok(Self {
+            dir,
+            format,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Store `json` for `id` unless an identical copy is already archived.
+    /// Returns `true` if a file was written.
+    pub fn store(&self, id: &str, json: &[u8]) -> Result<bool> {
+        let hash = format!("{:x}", Sha256::digest(json));
+        let path = self.dir.join(format!("{}.{}", id, self.format.extension()));
+
+        let unchanged = self
+            .index
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|h| *h == hash);
+        if unchanged && path.exists() {
+            return Ok(false);
+        }
+
+        let data = match self.format {
+            RawFormat::Zstd => zstd::encode_all(json, 19).wrap_err("zstd compression failed")?,
+            RawFormat::Json => json.to_vec(),
+        };
+        write_atomic(&path, &data).wrap_err("Failed to write raw archive")?;
+        // A copy in the other format is from before `--archive-raw` changed, and
+        // possibly older.
+        let other = self
+            .dir
+            .join(format!("{}.{}", id, self.format.other().extension()));
+        if other.exists() {
+            fs::remove_file(&other)
+                .wrap_err_with(|| format!("Failed to remove: {}", other.display()))?;
+        }
+        self.index.lock().unwrap().insert(id.to_string(), hash);
+        Ok(true)
+    }
+
+    /// Persist the id → hash index.
+    pub fn save(&self) -> Result<()> {
+        let index = self.index.lock().unwrap();
+        let json = serde_json::to_vec_pretty(&*index)?;
+        write_atomic(&self.dir.join(Self::INDEX), &json)
+            .wrap_err("Failed to write raw archive index")
+    }
+}
+
+/// Read every archived thread in `raw_dir` as `(id, json)`, accepting both formats.
+/// A file that cannot be read or decompressed is returned as an error for its id. If
+/// a thread is archived in both formats, the copy matching `index.json` wins.
+pub fn read_archive(raw_dir: &Path) -> Result<Vec<(String, Result<Vec<u8>>)>> {
+    let index: BTreeMap<String, String> = fs::read(raw_dir.join(RawArchive::INDEX))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default();
+
+    let mut files: BTreeMap<String, Vec<(PathBuf, bool)>> = BTreeMap::new();
+    for entry in fs::read_dir(raw_dir)
+        .wrap_err_with(|| format!("Failed to read: {}", raw_dir.display()))?
+        .flatten()
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let (id, compressed) = if let Some(id) = name.strip_suffix(".json.zst") {
+            (id, true)
+        } else if let Some(id) = name.strip_suffix(".json") {
+            (id, false)
+        } else {
+            continue;
+        };
+        if name == RawArchive::INDEX {
+            continue;
+        }
+        files
+            .entry(id.to_string())
+            .or_default()
+            .push((entry.path(), compressed));
+    }
+
+    let mut entries = Vec::new();
+    for (id, mut copies) in files {
+        // Compressed first, so the choice does not depend on directory order.
+        copies.sort_by_key(|(_, compressed)| !compressed);
+        let mut jsons: Vec<Result<Vec<u8>>> = copies
+            .iter()
+            .map(|(path, compressed)| read_raw(path, *compressed))
+            .collect();
+        let indexed = jsons
+            .iter()
+            .position(|json| {
+                json.as_ref()
+                    .is_ok_and(|j| index.get(&id) == Some(&format!("{:x}", Sha256::digest(j))))
+            })
+            .unwrap_or(0);
+        entries.push((id, jsons.swap_remove(indexed)));
+    }
+    Ok(entries)
+}
+
+fn read_raw(path: &Path, compressed: bool) -> Result<Vec<u8>> {
+    let data = fs::read(path).wrap_err_with(|| format!("Failed to read: {}", path.display()))?;
+    if compressed {
+        zstd::decode_all(data.as_slice())
+            .wrap_err_with(|| format!("Failed to decompress: {}", path.display()))
+    } else {
+        Ok(data)
+    }
+}
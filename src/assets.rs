@@ -0,0 +1,301 @@
+use crate::edits::written_hash;
+use crate::manifest::{Manifest, scan_asset_links};
+use crate::utils::{TEMP_PREFIX, write_atomic};
+use clap::ValueEnum;
+use eyre::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat as Encoding};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Directory under the target directory holding every exported image.
+pub const DIR: &str = "assets";
+
+/// JPEG quality for images converted with `--image-format jpeg`.
+const JPEG_QUALITY: u8 = 85;
+
+/// Format PNG images are stored in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// Keep the format Zed stored.
+    #[default]
+    Original,
+    /// Lossless WebP.
+    Webp,
+    /// JPEG at quality 85; drops transparency.
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn is_original(&self) -> bool {
+        *self == ImageFormat::Original
+    }
+}
+
+/// Shrink an image for storage: downscale it to fit `max_dimension` pixels on its
+/// longer side, and re-encode PNGs as `format`. A conversion that would not make the
+/// file smaller is dropped, and images the exporter cannot decode are kept as-is.
+pub fn shrink_image(bytes: Vec<u8>, max_dimension: Option<u32>, format: ImageFormat) -> Vec<u8> {
+    if max_dimension.is_none() && format.is_original() {
+        return bytes;
+    }
+    let Some(encoding) = image::guess_format(&bytes)
+        .ok()
+        .filter(|e| matches!(e, Encoding::Png | Encoding::Jpeg | Encoding::WebP))
+    else {
+        return bytes;
+    };
+    let convert = encoding == Encoding::Png && !format.is_original();
+    let Ok(img) = image::load_from_memory_with_format(&bytes, encoding) else {
+        return bytes;
+    };
+    let downscale = max_dimension.filter(|&max| img.width().max(img.height()) > max);
+    if downscale.is_none() && !convert {
+        return bytes;
+    }
+
+    let img = match downscale {
+        Some(max) => img.resize(max, max, FilterType::Lanczos3),
+        None => img,
+    };
+    let target = match (convert, format) {
+        (true, ImageFormat::Webp) => Encoding::WebP,
+        (true, ImageFormat::Jpeg) => Encoding::Jpeg,
+        _ => encoding,
+    };
+    match encode(&img, target) {
+        Some(out) if downscale.is_some() || out.len() < bytes.len() => out,
+        _ => bytes,
+    }
+}
+
+/// Re-encode an image stored as WebP or JPEG (see [`ImageFormat`]) as the PNG Zed
+/// expects. Anything else is returned unchanged.
+pub fn to_png(bytes: Vec<u8>) -> Vec<u8> {
+    match image::guess_format(&bytes) {
+        Ok(encoding @ (Encoding::WebP | Encoding::Jpeg)) => {
+            image::load_from_memory_with_format(&bytes, encoding)
+                .ok()
+                .and_then(|img| encode(&img, Encoding::Png))
+                .unwrap_or(bytes)
+        }
+        _ => bytes,
+    }
+}
+
+fn encode(img: &DynamicImage, encoding: Encoding) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let result = match encoding {
+        // Lossy, and without an alpha channel.
+        Encoding::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY)),
+        // The encoder only takes 8-bit RGB(A).
+        Encoding::WebP => DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+        _ => img.write_to(&mut Cursor::new(&mut out), encoding),
+    };
+    result.ok().map(|()| out)
+}
+
+/// File name of an asset: the SHA-256 of its bytes, so the same image pasted into
+/// several threads is stored once.
+pub fn content_name(bytes: &[u8], ext: &str) -> String {
+    format!("{:x}.{}", Sha256::digest(bytes), ext)
+}
+
+pub fn is_content_name(name: &str) -> bool {
+    let hash = name.split_once('.').map_or(name, |(hash, _)| hash);
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Regular files in `dir`, excluding temp files of an interrupted write.
+fn files_in(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| !n.starts_with(TEMP_PREFIX))
+        .collect()
+}
+
+/// Subdirectories of `assets/` holding mention snapshots, one per exported file.
+fn snapshot_dirs(target_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(target_dir.join(DIR)) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+/// Every asset, as its name relative to `assets/`: images at the top level and
+/// mention snapshots as `<stem>/<file>`.
+fn asset_files(target_dir: &Path) -> Vec<String> {
+    let assets_dir = target_dir.join(DIR);
+    let mut names = files_in(&assets_dir);
+    for dir in snapshot_dirs(target_dir) {
+        names.extend(
+            files_in(&assets_dir.join(&dir))
+                .into_iter()
+                .map(|name| format!("{}/{}", dir, name)),
+        );
+    }
+    names
+}
+
+/// Every markdown file in `target_dir`, conflict copies included.
+fn markdown_files(target_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(target_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| n.ends_with(".md"))
+        .collect()
+}
+
+/// Assets linked from an export: those the manifest recorded for each thread, plus
+/// those of markdown files it does not track (conflict copies).
+fn referenced(target_dir: &Path, manifest: &Manifest) -> BTreeSet<String> {
+    let tracked: BTreeSet<&str> = manifest.threads.values().map(|e| e.path.as_str()).collect();
+    let mut referenced: BTreeSet<String> = manifest
+        .threads
+        .values()
+        .flat_map(|e| e.assets.iter().cloned())
+        .collect();
+    for name in markdown_files(target_dir) {
+        if !tracked.contains(name.as_str()) {
+            referenced.extend(scan_asset_links(&target_dir.join(name)));
+        }
+    }
+    referenced
+}
+
+/// Asset names the manifest lists for any thread. Taken before a run changes it, it
+/// also covers the assets of threads the run updates or prunes.
+pub fn recorded(manifest: &Manifest) -> BTreeSet<String> {
+    manifest
+        .threads
+        .values()
+        .flat_map(|e| e.assets.iter().cloned())
+        .collect()
+}
+
+/// Remove the files in `assets/` that the exporter wrote but no exported markdown
+/// file links to any more, such as the images of pruned threads. Only content-named
+/// images and names in `recorded` (see [`recorded`]) are candidates, so files put
+/// there by hand stay. Returns the names removed, sorted.
+pub fn collect_garbage(
+    target_dir: &Path,
+    manifest: &Manifest,
+    recorded: &BTreeSet<String>,
+) -> Result<Vec<String>> {
+    let referenced = referenced(target_dir, manifest);
+    let mut removed: Vec<String> = asset_files(target_dir)
+        .into_iter()
+        .filter(|name| is_content_name(name) || recorded.contains(name))
+        .filter(|name| !referenced.contains(name))
+        .collect();
+    removed.sort();
+    for name in &removed {
+        let path = target_dir.join(DIR).join(name);
+        fs::remove_file(&path)
+            .wrap_err_with(|| format!("Failed to remove unreferenced asset: {}", path.display()))?;
+    }
+    for dir in snapshot_dirs(target_dir) {
+        // Fails, harmlessly, unless the directory is now empty.
+        let _ = fs::remove_dir(target_dir.join(DIR).join(dir));
+    }
+    Ok(removed)
+}
+
+/// Rename assets written by older exporters (`<stem>.<hash>.<ext>`) to their content
+/// name and rewrite the links to them, so existing exports match what the renderer
+/// now writes without re-rendering them. Only images the manifest lists are renamed.
+/// Files that were unedited stay unedited: their recorded written hash is updated
+/// along with the links. Returns the number of assets renamed.
+pub fn migrate(target_dir: &Path, manifest: &mut Manifest) -> Result<usize> {
+    let assets_dir = target_dir.join(DIR);
+    let recorded = recorded(manifest);
+    let mut renamed = BTreeMap::new();
+    for name in files_in(&assets_dir) {
+        if is_content_name(&name) || !recorded.contains(&name) {
+            continue;
+        }
+        let old = assets_dir.join(&name);
+        let bytes =
+            fs::read(&old).wrap_err_with(|| format!("Failed to read asset: {}", old.display()))?;
+        let ext = Path::new(&name)
+            .extension()
+            .map_or("bin".into(), |e| e.to_string_lossy());
+        let new_name = content_name(&bytes, &ext);
+        let new = assets_dir.join(&new_name);
+        if new.exists() {
+            fs::remove_file(&old)
+        } else {
+            fs::rename(&old, &new)
+        }
+        .wrap_err_with(|| format!("Failed to migrate asset: {}", old.display()))?;
+        renamed.insert(name, new_name);
+    }
+    if renamed.is_empty() {
+        return Ok(0);
+    }
+
+    let by_path: BTreeMap<String, String> = manifest
+        .threads
+        .iter()
+        .map(|(id, e)| (e.path.clone(), id.clone()))
+        .collect();
+    for name in markdown_files(target_dir) {
+        let path = target_dir.join(&name);
+        let Ok(bytes) = fs::read(&path) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        let mut rewritten = text.to_string();
+        for (old, new) in &renamed {
+            rewritten = rewritten.replace(
+                &format!("](./{}/{})", DIR, old),
+                &format!("](./{}/{})", DIR, new),
+            );
+        }
+        if rewritten == text {
+            continue;
+        }
+        write_atomic(&path, rewritten.as_bytes())?;
+
+        if let Some(entry) = by_path
+            .get(&name)
+            .and_then(|id| manifest.threads.get_mut(id))
+            && entry.written_hash == written_hash(&bytes)
+        {
+            entry.written_hash = written_hash(rewritten.as_bytes());
+        }
+    }
+    for entry in manifest.threads.values_mut() {
+        for asset in &mut entry.assets {
+            if let Some(new) = renamed.get(asset) {
+                *asset = new.clone();
+            }
+        }
+        entry.assets.sort();
+        entry.assets.dedup();
+    }
+    manifest.save(target_dir)?;
+    Ok(renamed.len())
+}
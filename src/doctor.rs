@@ -0,0 +1,426 @@
+use crate::importer::{
+    AgentMessageContent, DbThread, MentionUri, Message, UserMessageContent, variant_name,
+};
+use crate::schema::{self, SchemaName, Schemas, ThreadDocument};
+use crate::utils::{decompress, open_db};
+use eyre::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Longest sample printed for a failure, in bytes.
+const SAMPLE_LIMIT: usize = 600;
+
+/// Findings of a `doctor` run.
+#[derive(Default)]
+pub struct Diagnosis {
+    /// `(name, declared type)` of each column of `threads`.
+    pub columns: Vec<(String, String)>,
+    /// The `--zed-schema` rows of unknown versions were read with.
+    pub forced: Option<SchemaName>,
+    pub rows_by_data_type: BTreeMap<String, usize>,
+    /// Row counts keyed by the JSON `version` field, `(none)` if missing.
+    pub rows_by_version: BTreeMap<String, usize>,
+    pub db_threads: usize,
+    pub serialized_threads: usize,
+    /// Rows whose data could not be decompressed or is not JSON.
+    pub unreadable: Vec<(String, String)>,
+    /// Deserialization failures, grouped by where and why they happened.
+    pub failures: BTreeMap<(String, String), Failure>,
+    /// Enum variants the importer does not know, grouped by location and variant
+    /// name. These threads still export, with the unknown parts as raw JSON.
+    pub unsupported: BTreeMap<(String, String), Failure>,
+}
+
+/// One kind of deserialization failure (or unsupported variant) and the threads it
+/// affects.
+pub struct Failure {
+    pub thread_ids: Vec<String>,
+    /// Redacted JSON around the failing location, from the first thread affected.
+    pub sample: Option<String>,
+}
+
+impl Diagnosis {
+    pub fn missing_columns(&self) -> Vec<&str> {
+        schema::missing_columns(&self.columns)
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.missing_columns().is_empty() && self.unreadable.is_empty() && self.failures.is_empty()
+    }
+}
+
+/// Inspect the `threads` table of `db_path` and try to deserialize every row with
+/// the schemas an export with `forced` as `--zed-schema` would use.
+pub fn diagnose(db_path: &Path, forced: Option<SchemaName>) -> Result<Diagnosis> {
+    let conn = open_db(db_path)?;
+    let mut diagnosis = Diagnosis {
+        columns: schema::table_columns(&conn)?,
+        forced,
+        ..Default::default()
+    };
+    if diagnosis.columns.is_empty() {
+        return Err(eyre::eyre!("No `threads` table in {}", db_path.display()));
+    }
+    if !diagnosis.missing_columns().is_empty() {
+        return Ok(diagnosis);
+    }
+    let schemas = Schemas::detect(&conn, forced)?;
+
+    let mut stmt = conn.prepare("SELECT id, data_type, data FROM threads")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let data_type: String = row.get(1)?;
+        let data: Vec<u8> = row.get(2)?;
+        *diagnosis
+            .rows_by_data_type
+            .entry(data_type.clone())
+            .or_default() += 1;
+
+        let json = match decompress(&data_type, &data) {
+            Ok(json) => json,
+            Err(e) => {
+                diagnosis.unreadable.push((id, format!("{:#}", e)));
+                continue;
+            }
+        };
+        let value: Value = match serde_json::from_slice(&json) {
+            Ok(v) => v,
+            Err(e) => {
+                diagnosis
+                    .unreadable
+                    .push((id, format!("Invalid JSON: {}", e)));
+                continue;
+            }
+        };
+
+        let version = value.get("version").and_then(Value::as_str);
+        *diagnosis
+            .rows_by_version
+            .entry(version.unwrap_or("(none)").to_string())
+            .or_default() += 1;
+
+        // The first candidate's error is the one the export reports.
+        let mut first_error = None;
+        let parsed = schemas.candidates(version).into_iter().find_map(|schema| {
+            schema
+                .check(&json)
+                .map_err(|e| {
+                    first_error.get_or_insert((
+                        e.path().to_string(),
+                        strip_position(&e.inner().to_string()),
+                    ))
+                })
+                .ok()
+        });
+        match parsed {
+            Some(ThreadDocument::Agent(thread)) => {
+                diagnosis.db_threads += 1;
+                for (path, value) in unknown_variants(&thread) {
+                    let name = variant_name(value).unwrap_or("?").to_string();
+                    diagnosis
+                        .unsupported
+                        .entry((path.to_string(), name))
+                        .or_insert_with(|| Failure {
+                            thread_ids: Vec::new(),
+                            sample: Some(truncate(redact(value).to_string())),
+                        })
+                        .thread_ids
+                        .push(id.clone());
+                }
+                continue;
+            }
+            Some(_) => {
+                diagnosis.serialized_threads += 1;
+                continue;
+            }
+            None => {}
+        }
+
+        let (path, message) = first_error.expect("at least one candidate schema");
+        let failure = diagnosis
+            .failures
+            .entry((generalize_path(&path), message))
+            .or_insert_with(|| Failure {
+                thread_ids: Vec::new(),
+                sample: sample_at(&value, &path),
+            });
+        failure.thread_ids.push(id);
+    }
+
+    Ok(diagnosis)
+}
+
+/// Every value captured by an `Unknown` variant in `thread`, with its location.
+fn unknown_variants(thread: &DbThread) -> Vec<(&'static str, &Value)> {
+    let mut found = Vec::new();
+    for message in &thread.messages {
+        match message {
+            Message::Unknown(v) => found.push(("messages[]", v)),
+            Message::User(user) => {
+                for content in &user.content {
+                    match content {
+                        UserMessageContent::Unknown(v) => {
+                            found.push(("messages[].User.content[]", v))
+                        }
+                        UserMessageContent::Mention {
+                            uri: MentionUri::Unknown(v),
+                            ..
+                        } => found.push(("messages[].User.content[].Mention.uri", v)),
+                        _ => {}
+                    }
+                }
+            }
+            Message::Agent(agent) => {
+                for content in &agent.content {
+                    if let AgentMessageContent::Unknown(v) = content {
+                        found.push(("messages[].Agent.content[]", v));
+                    }
+                }
+            }
+            Message::Resume => {}
+        }
+    }
+    found
+}
+
+/// Drop serde_json's " at line L column C" suffix, which differs for every thread.
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// `messages[12].User.content[3]` → `messages[].User.content[]`, so failures at the
+/// same field in different threads group together.
+fn generalize_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                out.push(c);
+            }
+            ']' => {
+                in_index = false;
+                out.push(c);
+            }
+            _ if in_index => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Redacted JSON of the deepest value reachable along `path`, or of its parent
+/// when that value is a scalar, so the shape around the failure is visible.
+fn sample_at(root: &Value, path: &str) -> Option<String> {
+    let mut trail = vec![root];
+    for segment in path_segments(path) {
+        let current = *trail.last()?;
+        let next = match (&segment, current) {
+            (Segment::Index(i), Value::Array(items)) => items.get(*i),
+            (Segment::Key(k), Value::Object(map)) => map.get(k),
+            _ => None,
+        };
+        match next {
+            Some(v) => trail.push(v),
+            None => break,
+        }
+    }
+    let mut target = *trail.last()?;
+    if !(target.is_object() || target.is_array()) && trail.len() > 1 {
+        target = trail[trail.len() - 2];
+    }
+
+    Some(truncate(redact(target).to_string()))
+}
+
+fn truncate(mut sample: String) -> String {
+    if sample.len() > SAMPLE_LIMIT {
+        let mut end = SAMPLE_LIMIT;
+        while !sample.is_char_boundary(end) {
+            end -= 1;
+        }
+        sample.truncate(end);
+        sample.push('…');
+    }
+    sample
+}
+
+enum Segment {
+    Index(usize),
+    Key(String),
+}
+
+/// Split a serde_path_to_error path (`a.b[2].c`) into segments.
+fn path_segments(path: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    for part in path.split('.').filter(|p| !p.is_empty() && *p != "?") {
+        let (key, indices) = part.split_once('[').unwrap_or((part, ""));
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        }
+        for index in indices.split('[') {
+            if let Ok(i) = index.trim_end_matches(']').parse() {
+                segments.push(Segment::Index(i));
+            }
+        }
+    }
+    segments
+}
+
+/// Replace every string value with its length, keeping object keys (which carry
+/// enum variant names), the `version`/`type`/`role` fields, numbers and booleans.
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(format!("<{} chars>", s.chars().count())),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| {
+                    let v = match (k.as_str(), v) {
+                        ("version" | "type" | "role", Value::String(_)) => v.clone(),
+                        _ => redact(v),
+                    };
+                    (k.clone(), v)
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Print `diagnosis` for humans. `verbose` lists every affected thread id.
+pub fn print(db_path: &Path, diagnosis: &Diagnosis, verbose: bool) {
+    println!("Database: {}", db_path.display());
+    let columns: Vec<String> = diagnosis
+        .columns
+        .iter()
+        .map(|(name, ty)| format!("{} {}", name, ty))
+        .collect();
+    println!("Columns:  {}", columns.join(", "));
+    let missing = diagnosis.missing_columns();
+    if !missing.is_empty() {
+        println!("  MISSING: {}", missing.join(", "));
+        println!("The threads table does not have the layout this exporter expects.");
+        return;
+    }
+    let extra: Vec<&str> = diagnosis
+        .columns
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| !schema::COLUMNS.contains(name))
+        .collect();
+    if !extra.is_empty() {
+        println!("  Not read (ignored): {}", extra.join(", "));
+    }
+
+    let total: usize = diagnosis.rows_by_data_type.values().sum();
+    println!(
+        "Rows:     {} ({})",
+        total,
+        format_counts(&diagnosis.rows_by_data_type)
+    );
+    let versions: Vec<String> = diagnosis
+        .rows_by_version
+        .iter()
+        .map(|(version, n)| match schema::for_version(version) {
+            Some(s) => format!("{}: {} ({})", version, n, s.name),
+            None => match diagnosis.forced {
+                Some(forced) => format!("{}: {} (unknown, read as {})", version, n, forced),
+                None => format!("{}: {} (unknown)", version, n),
+            },
+        })
+        .collect();
+    println!("Versions: {}", versions.join(", "));
+    println!("Schemas:");
+    for schema in &schema::REGISTRY {
+        println!(
+            "  {:<7} JSON {} (Zed: {})",
+            schema.name,
+            schema.json_versions.join(", "),
+            schema.zed_versions
+        );
+    }
+    let failed: usize = diagnosis
+        .failures
+        .values()
+        .map(|f| f.thread_ids.len())
+        .sum();
+    println!(
+        "Parsed:   {} DbThread, {} SerializedThread, {} failed, {} unreadable",
+        diagnosis.db_threads,
+        diagnosis.serialized_threads,
+        failed,
+        diagnosis.unreadable.len()
+    );
+
+    for ((path, message), failure) in &diagnosis.failures {
+        println!();
+        println!("[{} thread(s)] at `{}`", failure.thread_ids.len(), path);
+        println!("  error:  {}", message);
+        if verbose {
+            println!("  ids:    {}", failure.thread_ids.join(", "));
+        } else {
+            println!("  e.g.:   {}", failure.thread_ids[0]);
+        }
+        if let Some(sample) = &failure.sample {
+            println!("  sample: {}", sample);
+        }
+    }
+
+    for ((path, name), unsupported) in &diagnosis.unsupported {
+        println!();
+        println!(
+            "[{} thread(s)] unsupported variant `{}` at `{}` (exported as raw JSON)",
+            unsupported.thread_ids.len(),
+            name,
+            path
+        );
+        if verbose {
+            println!("  ids:    {}", unsupported.thread_ids.join(", "));
+        } else {
+            println!("  e.g.:   {}", unsupported.thread_ids[0]);
+        }
+        if let Some(sample) = &unsupported.sample {
+            println!("  sample: {}", sample);
+        }
+    }
+
+    for (id, error) in &diagnosis.unreadable {
+        println!();
+        println!("[unreadable] {}: {}", id, error);
+    }
+
+    println!();
+    if diagnosis.is_healthy() && !diagnosis.unsupported.is_empty() {
+        println!("All threads can be exported, but some content is newer than this exporter.");
+    } else if diagnosis.is_healthy() {
+        println!("All threads can be exported.");
+    } else {
+        let unknown = diagnosis
+            .rows_by_version
+            .keys()
+            .any(|v| schema::for_version(v).is_none());
+        if unknown && diagnosis.forced.is_none() {
+            println!(
+                "Some rows have a JSON version this exporter does not know. If the format is compatible, `--zed-schema agent` reads them anyway."
+            );
+        }
+        println!(
+            "Some threads cannot be exported. Please include this output (it contains no message text) in a bug report."
+        );
+    }
+}
+
+fn format_counts(counts: &BTreeMap<String, usize>) -> String {
+    counts
+        .iter()
+        .map(|(k, n)| format!("{}: {}", k, n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,137 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Start/end markers of a user-notes section. Text between them is carried over
+/// every time the file is re-exported.
+pub const NOTES_START: &str = "<!-- zed-chat-export:notes -->";
+pub const NOTES_END: &str = "<!-- /zed-chat-export:notes -->";
+
+/// Suffix of the file written next to a locally modified export under `conflict`.
+pub const CONFLICT_SUFFIX: &str = ".conflict.md";
+
+/// What to do when an exported file was edited since it was last written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EditPolicy {
+    /// Leave the edited file untouched and print a warning.
+    #[default]
+    Skip,
+    /// Leave the edited file untouched and write the new export as `<name>.conflict.md`.
+    Conflict,
+    /// Overwrite the file, keeping only its user-notes section.
+    KeepNotes,
+}
+
+/// Outcome of checking an existing export for local modifications.
+pub enum EditCheck {
+    /// Write to the given path, appending the preserved notes section if any.
+    Write {
+        path: PathBuf,
+        notes: Option<String>,
+    },
+    /// The file was modified and the policy says to leave it alone.
+    Skip,
+}
+
+/// SHA-256 of a file's bytes as written, leaving out its user-notes section so that
+/// adding notes does not count as an edit.
+pub fn written_hash(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    format!("{:x}", Sha256::digest(strip_notes(&text).as_bytes()))
+}
+
+/// Decide where the new export goes given the file currently on disk (`existing`),
+/// the hash recorded when it was last written, the freshly `rendered` markdown and
+/// the policy.
+pub fn check_user_edits(
+    existing: Option<&Path>,
+    last_written: Option<&str>,
+    rendered: &[u8],
+    desired: &Path,
+    policy: EditPolicy,
+) -> EditCheck {
+    let Some(bytes) = existing.and_then(|p| fs::read(p).ok()) else {
+        return EditCheck::Write {
+            path: desired.to_path_buf(),
+            notes: None,
+        };
+    };
+    let notes = extract_notes(&String::from_utf8_lossy(&bytes));
+
+    let modified = if last_written.is_none_or(str::is_empty) {
+        // Nothing recorded (a manifest rebuilt from disk): the file is ours only if it
+        // is exactly what this render writes, as it is for an unchanged thread.
+        written_hash(&bytes) != written_hash(rendered)
+    } else {
+        is_modified(&bytes, last_written)
+    };
+    if !modified {
+        return EditCheck::Write {
+            path: desired.to_path_buf(),
+            notes,
+        };
+    }
+
+    match policy {
+        EditPolicy::Skip => EditCheck::Skip,
+        EditPolicy::Conflict => EditCheck::Write {
+            path: conflict_path(desired),
+            notes,
+        },
+        EditPolicy::KeepNotes => EditCheck::Write {
+            path: desired.to_path_buf(),
+            notes,
+        },
+    }
+}
+
+/// Whether `bytes` differ from what was last written, notes aside. With no recorded
+/// hash (e.g. a manifest rebuilt from disk) nothing vouches for the file, so it counts
+/// as modified.
+pub fn is_modified(bytes: &[u8], last_written: Option<&str>) -> bool {
+    last_written.is_none_or(|h| h.is_empty() || h != written_hash(bytes))
+}
+
+fn conflict_path(desired: &Path) -> PathBuf {
+    let name = desired.file_name().unwrap_or_default().to_string_lossy();
+    let stem = name.strip_suffix(".md").unwrap_or(&name);
+    desired.with_file_name(format!("{}{}", stem, CONFLICT_SUFFIX))
+}
+
+/// Byte range of the user-notes section of `text`, markers included.
+fn notes_range(text: &str) -> Option<Range<usize>> {
+    let start = text.find(NOTES_START)?;
+    let end = text[start..].find(NOTES_END)? + start + NOTES_END.len();
+    Some(start..end)
+}
+
+/// Return the user-notes section of `text`, markers included.
+pub fn extract_notes(text: &str) -> Option<String> {
+    notes_range(text).map(|range| text[range].to_string())
+}
+
+/// Remove the user-notes section from `text`, if present, with the line break that
+/// ends it.
+pub fn strip_notes(text: &str) -> String {
+    match notes_range(text) {
+        Some(Range { start, mut end }) => {
+            if text[end..].starts_with('\n') {
+                end += 1;
+            }
+            format!("{}{}", &text[..start], &text[end..])
+        }
+        None => text.to_string(),
+    }
+}
+
+/// Append a preserved notes section to freshly rendered markdown.
+pub fn append_notes(buf: &mut Vec<u8>, notes: Option<&str>) {
+    if let Some(notes) = notes {
+        buf.extend_from_slice(notes.as_bytes());
+        buf.push(b'\n');
+    }
+}
@@ -0,0 +1,366 @@
+//! The export run: everything between reading the database and reporting the
+//! outcome, shared by both executors.
+//!
+//! A run snapshots the database, loads the manifest, assigns every thread its file
+//! name up front, then hands one job per thread to the executor: the worker pool in
+//! [`crate::parallel`], or with the `sequential` feature the single-threaded loop
+//! with a progress bar in [`crate::sequential`]. Deciding and writing each thread,
+//! pruning, asset collection and reporting happen here, whichever executor runs.
+
+use crate::archive::RawArchive;
+use crate::assets;
+use crate::manifest::Manifest;
+use crate::plan::{self, Change, Decision, Outcome, Plan, prune};
+use crate::report::Report;
+use crate::schema::{Schemas, ThreadDocument, ThreadSource};
+use crate::text_thread::{self, TextThreadFile};
+use crate::utils::{self, ExportConfig, backup_database, decompress, open_db};
+use chrono::Utc;
+use eyre::{Context, Result};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+#[cfg(not(feature = "sequential"))]
+use crate::parallel as executor;
+#[cfg(feature = "sequential")]
+use crate::sequential as executor;
+
+/// Claims a file name prefix for each thread: the first 8 characters of its id,
+/// or 12, or the whole id when a shorter prefix belongs to another thread.
+pub struct FileNames {
+    /// Prefix → id of the thread that owns it.
+    owners: HashMap<String, String>,
+}
+
+impl FileNames {
+    /// Start from the files already exported, so a new thread never claims a prefix
+    /// owned by an existing file. The prefix is the part of the file name before the
+    /// first `_` (or before `.md` if there is none).
+    pub fn new(manifest: &Manifest) -> Self {
+        let mut owners = HashMap::new();
+        for (id, entry) in &manifest.threads {
+            let stem = entry.path.trim_end_matches(".md");
+            let prefix = stem.split('_').next().unwrap_or(stem);
+            if !prefix.is_empty() {
+                owners.insert(prefix.to_string(), id.clone());
+            }
+        }
+        Self { owners }
+    }
+
+    /// File stem for thread `id`: `<prefix>_<slug of title>`.
+    pub fn allocate(&mut self, id: &str, title: &str) -> String {
+        let raw_slug = slug::slugify(title);
+        let slug = raw_slug[..raw_slug.len().min(60)].trim_end_matches('-');
+        let stem = |prefix: &str| {
+            if slug.is_empty() {
+                prefix.to_string()
+            } else {
+                format!("{}_{}", prefix, slug)
+            }
+        };
+
+        for len in [8, 12] {
+            let prefix = &id[..len.min(id.len())];
+            match self.owners.get(prefix) {
+                None => {
+                    self.owners.insert(prefix.to_string(), id.to_string());
+                    return stem(prefix);
+                }
+                Some(owner) if owner == id => return stem(prefix),
+                Some(_) => continue,
+            }
+        }
+        // A UUID is unique, so the full id is always free.
+        stem(id)
+    }
+}
+
+/// Where a job's thread comes from.
+enum Source {
+    /// A row of the `threads` table.
+    Database,
+    /// An index into the text thread files.
+    TextThread(usize),
+}
+
+/// One thread to export.
+struct Job {
+    id: String,
+    /// File stem to write to, if the thread is not skipped.
+    stem: String,
+    source: Source,
+}
+
+/// State shared by every job of a run.
+struct Run<'a> {
+    config: &'a ExportConfig,
+    schemas: &'a Schemas,
+    archive: Option<&'a RawArchive>,
+    text_threads: &'a [TextThreadFile],
+    manifest: Mutex<Manifest>,
+    plan: Mutex<Plan>,
+    /// Raised once `--max-errors` is reached.
+    stop: AtomicBool,
+    progress: &'a executor::Progress,
+}
+
+pub fn execute(config: ExportConfig) -> Result<Outcome> {
+    let started_at = Utc::now();
+    let progress = executor::Progress::new(config.quiet);
+    let snapshot = backup_database(&config.db_path)?;
+
+    let archive = if config.dry_run {
+        None
+    } else {
+        fs::create_dir_all(&config.target_dir).wrap_err_with(|| {
+            format!(
+                "Failed to create target directory: {}",
+                config.target_dir.display()
+            )
+        })?;
+        fs::create_dir_all(config.target_dir.join(assets::DIR))
+            .wrap_err("Failed to create assets directory")?;
+        for dir in ["", assets::DIR, Manifest::DIR] {
+            utils::remove_stale_temp_files(&config.target_dir.join(dir));
+        }
+        config
+            .archive_raw
+            .map(|format| RawArchive::open(&config.target_dir, format))
+            .transpose()?
+    };
+
+    let (threads, schemas) = {
+        let conn = open_db(snapshot.path())?;
+        let schemas = Schemas::detect(&conn, config.zed_schema)?;
+        let mut stmt = conn
+            .prepare("SELECT id, summary FROM threads ORDER BY updated_at DESC")
+            .wrap_err("Failed to prepare id query")?;
+        let threads: Vec<(String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()
+            .wrap_err("Failed to collect ids")?;
+        (threads, schemas)
+    };
+    let text_threads = match &config.text_threads_dir {
+        Some(dir) => text_thread::discover(dir)?,
+        None => Vec::new(),
+    };
+
+    let mut manifest = Manifest::load(&config.target_dir);
+    if !config.dry_run {
+        let migrated = assets::migrate(&config.target_dir, &mut manifest)?;
+        if migrated > 0 && !config.quiet {
+            progress.println(&format!(
+                "Renamed {} asset(s) to content-addressed names.",
+                migrated
+            ));
+        }
+    }
+    let recorded_assets = assets::recorded(&manifest);
+
+    // Names are assigned in job order, before any work starts, so which thread gets
+    // a contested prefix does not depend on scheduling.
+    let mut names = FileNames::new(&manifest);
+    let mut jobs: Vec<Job> = threads
+        .iter()
+        .map(|(id, title)| Job {
+            id: id.clone(),
+            stem: names.allocate(id, title),
+            source: Source::Database,
+        })
+        .collect();
+    jobs.extend(text_threads.iter().enumerate().map(|(i, file)| Job {
+        id: file.id.clone(),
+        stem: names.allocate(&file.id, &file.title),
+        source: Source::TextThread(i),
+    }));
+
+    let run = Run {
+        config: &config,
+        schemas: &schemas,
+        archive: archive.as_ref(),
+        text_threads: &text_threads,
+        manifest: Mutex::new(manifest),
+        plan: Mutex::new(Plan::default()),
+        stop: AtomicBool::new(false),
+        progress: &progress,
+    };
+    progress.start(jobs.len() as u64);
+    let result = executor::run(
+        &jobs,
+        || open_db(snapshot.path()),
+        |conn, job| run.export(conn, job),
+        &run.stop,
+    );
+    progress.finish();
+
+    let mut manifest = run.manifest.into_inner().unwrap();
+    let mut plan = run.plan.into_inner().unwrap();
+    plan.aborted = run.stop.into_inner();
+
+    // The plan is incomplete after an early stop, so nothing may be pruned.
+    if config.prune && result.is_ok() && !plan.aborted {
+        // Text threads were not looked for, so their absence says nothing; shared
+        // threads were never in the database.
+        let unscanned: Vec<String> = manifest
+            .threads
+            .iter()
+            .filter(|(_, entry)| match entry.source {
+                ThreadSource::Database => false,
+                ThreadSource::TextThread => config.text_threads_dir.is_none(),
+                ThreadSource::Shared => true,
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let live: HashSet<&str> = jobs
+            .iter()
+            .map(|j| j.id.as_str())
+            .chain(unscanned.iter().map(String::as_str))
+            .collect();
+        for change in prune(&mut manifest, &live, &config)? {
+            if let Some(warning) = change.warning() {
+                eprintln!("{}", warning);
+            } else if config.verbose && !config.dry_run {
+                eprintln!("{}", change);
+            }
+            plan.push(change);
+        }
+    }
+
+    if !config.dry_run && result.is_ok() && !plan.aborted {
+        let removed = assets::collect_garbage(&config.target_dir, &manifest, &recorded_assets)?;
+        if config.verbose {
+            for name in &removed {
+                eprintln!("Removed unreferenced asset {}", name);
+            }
+        } else if !removed.is_empty() && !config.quiet {
+            eprintln!("Removed {} unreferenced asset(s).", removed.len());
+        }
+    }
+
+    if !config.dry_run {
+        // Persist state even if the run failed part-way, so finished exports are not redone.
+        manifest.save(&config.target_dir)?;
+        if let Some(archive) = archive {
+            archive.save()?;
+        }
+    }
+
+    if let Err(e) = &result {
+        plan.push_error(None, e);
+    }
+    if config.report.is_some() {
+        plan.sort();
+        Report::new(&plan, &config, started_at).write(&config)?;
+    }
+    result?;
+
+    if config.dry_run {
+        plan.print(&config)?;
+    } else if !config.quiet {
+        let mut summary = format!("Done. {}.", plan.summary());
+        if !plan.errors.is_empty() {
+            summary.push_str(&format!(" Completed with {} error(s).", plan.errors.len()));
+        }
+        eprintln!("{}", summary);
+    }
+    if plan.aborted && !config.quiet {
+        eprintln!("Stopped after {} error(s).", plan.errors.len());
+    }
+    Ok(plan.outcome())
+}
+
+impl Run<'_> {
+    /// Export one job's thread and record the outcome in the plan.
+    fn export(&self, conn: &mut Connection, job: &Job) {
+        match self.export_job(conn, job) {
+            Ok(change) => self.plan.lock().unwrap().push(change),
+            Err(e) => {
+                self.progress.println(&format!(
+                    "Error [{}]: {:#}",
+                    &job.id[..8.min(job.id.len())],
+                    e
+                ));
+                let mut plan = self.plan.lock().unwrap();
+                plan.push_error(Some(&job.id), &e);
+                if plan.error_limit_reached(self.config) {
+                    self.stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        self.progress.inc();
+    }
+
+    /// Decide what to do with one thread and, unless this is a dry run, do it.
+    fn export_job(&self, conn: &Connection, job: &Job) -> Result<Change> {
+        let started = Instant::now();
+        let decision = match job.source {
+            Source::Database => {
+                let (data_type, data): (String, Vec<u8>) = conn
+                    .query_row(
+                        "SELECT data_type, data FROM threads WHERE id = ?",
+                        [&job.id],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .wrap_err("Failed to fetch thread")?;
+                let json = decompress(&data_type, &data).wrap_err("Failed to decompress data")?;
+                if let Some(archive) = self.archive {
+                    archive.store(&job.id, &json)?;
+                }
+                self.decide(job, &json, |json| self.schemas.parse(json))?
+            }
+            Source::TextThread(i) => {
+                let file = &self.text_threads[i];
+                self.decide(job, &file.json, |json| text_thread::parse(json, file))?
+            }
+        };
+
+        let mut change = decision.change;
+        if !self.config.dry_run {
+            if let Some(write) = decision.write {
+                write.apply(&self.config.target_dir)?;
+            }
+            if let Some(entry) = decision.entry {
+                let mut manifest = self.manifest.lock().unwrap();
+                manifest.insert(&job.id, entry);
+                manifest.checkpoint(&self.config.target_dir)?;
+            }
+            if let Some(warning) = change.warning() {
+                self.progress.println(&warning);
+            } else if self.config.verbose {
+                self.progress.println(&change.to_string());
+            }
+        }
+        change.elapsed = started.elapsed();
+        Ok(change)
+    }
+
+    fn decide(
+        &self,
+        job: &Job,
+        json: &[u8],
+        parse: impl FnOnce(&[u8]) -> Result<ThreadDocument>,
+    ) -> Result<Decision> {
+        let (existing_path, last) = {
+            let manifest = self.manifest.lock().unwrap();
+            (
+                manifest.existing_path(&self.config.target_dir, &job.id),
+                manifest.get(&job.id).cloned(),
+            )
+        };
+        plan::decide(
+            &job.id,
+            json,
+            existing_path.as_deref(),
+            last.as_ref(),
+            self.config,
+            parse,
+            || job.stem.clone(),
+        )
+    }
+}
@@ -0,0 +1,671 @@
+use crate::importer::{
+    AgentMessage, AgentMessageContent, DbThread, DetailedSummaryState, GitState,
+    LanguageModelImage, LanguageModelToolResult, LanguageModelToolResultContent,
+    LanguageModelToolUse, MentionUri, Message, MessageId, ProjectSnapshot, Role, SerializedCrease,
+    SerializedLanguageModel, SerializedMessage, SerializedMessageSegment, SerializedThread,
+    SerializedToolResult, SerializedToolUse, SessionId, SubagentContext, THREADS_TABLE_SQL,
+    TelemetryWorktreeSnapshot, TokenUsage, UserMessage, UserMessageContent,
+};
+use crate::utils::NewDatabase;
+use base64::Engine as _;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use clap::{Args, ValueEnum};
+use eyre::{Context, Result, eyre};
+use image::{ImageFormat, Rgb, RgbImage};
+use rusqlite::{Connection, params};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/// What to put in a generated database.
+#[derive(Args, Clone, Debug)]
+pub struct GenerateOptions {
+    /// Agent threads, in the current `DbThread` format.
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    pub threads: usize,
+
+    /// Average messages per thread.
+    #[arg(long, value_name = "N", default_value_t = 12)]
+    pub messages: usize,
+
+    /// Tool calls per assistant message.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub tool_calls: usize,
+
+    /// Pasted images per thread.
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    pub images: usize,
+
+    /// @-mentions per thread, of every kind.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub mentions: usize,
+
+    /// Subagent threads, each spawned by a random agent thread.
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub subagents: usize,
+
+    /// Threads in the legacy `SerializedThread` format (JSON 0.1.0 / 0.2.0).
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub legacy: usize,
+
+    /// How rows store their JSON: all `json`, all `zstd`, or alternating.
+    #[arg(long, value_name = "TYPE", default_value = "mixed")]
+    pub data_type: StoredAs,
+
+    /// The same seed and options always produce the same threads.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub seed: u64,
+}
+
+/// Value of the `data_type` column for generated rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StoredAs {
+    Json,
+    Zstd,
+    Mixed,
+}
+
+pub struct GenerateSummary {
+    pub agent: usize,
+    pub subagents: usize,
+    pub legacy: usize,
+    pub json_rows: usize,
+    pub zstd_rows: usize,
+}
+
+impl GenerateSummary {
+    pub fn total(&self) -> usize {
+        self.agent + self.subagents + self.legacy
+    }
+}
+
+/// A generated row of the `threads` table.
+struct Row {
+    id: String,
+    parent_id: Option<String>,
+    summary: String,
+    updated_at: DateTime<Utc>,
+    json: Vec<u8>,
+}
+
+/// Write a fake Zed `threads.db` at `output`. Nothing in it comes from a real
+/// database, so it can be attached to bug reports and used for benchmarks.
+pub fn generate(output: &Path, options: &GenerateOptions, force: bool) -> Result<GenerateSummary> {
+    if options.subagents > 0 && options.threads == 0 {
+        return Err(eyre!("--subagents needs at least one agent thread"));
+    }
+    let db = NewDatabase::create(output, force)?;
+
+    let mut generator = Generator::new(options);
+    let mut rows = Vec::new();
+    for _ in 0..options.threads {
+        rows.push(generator.agent_thread(None)?);
+    }
+    for _ in 0..options.subagents {
+        let parent = rows[generator.rng.below(options.threads)].id.clone();
+        rows.push(generator.agent_thread(Some(parent))?);
+    }
+    for i in 0..options.legacy {
+        let version = if i % 2 == 0 { "0.2.0" } else { "0.1.0" };
+        rows.push(generator.legacy_thread(version)?);
+    }
+
+    let mut conn = Connection::open(db.path())
+        .wrap_err_with(|| format!("Failed to create: {}", output.display()))?;
+    conn.execute_batch(THREADS_TABLE_SQL)
+        .wrap_err("Failed to create threads table")?;
+
+    let mut summary = GenerateSummary {
+        agent: options.threads,
+        subagents: options.subagents,
+        legacy: options.legacy,
+        json_rows: 0,
+        zstd_rows: 0,
+    };
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO threads (id, parent_id, summary, updated_at, data_type, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        for (i, row) in rows.iter().enumerate() {
+            let zstd = match options.data_type {
+                StoredAs::Json => false,
+                StoredAs::Zstd => true,
+                StoredAs::Mixed => i % 2 == 0,
+            };
+            let (data_type, data) = if zstd {
+                summary.zstd_rows += 1;
+                let data = zstd::encode_all(row.json.as_slice(), 3)
+                    .wrap_err_with(|| format!("Failed to compress thread {}", row.id))?;
+                ("zstd", data)
+            } else {
+                summary.json_rows += 1;
+                ("json", row.json.clone())
+            };
+            stmt.execute(params![
+                row.id,
+                row.parent_id,
+                row.summary,
+                row.updated_at.to_rfc3339(),
+                data_type,
+                data
+            ])?;
+        }
+    }
+    tx.commit().wrap_err("Failed to commit generated threads")?;
+    drop(conn);
+    db.persist()?;
+
+    Ok(summary)
+}
+
+/// Small deterministic PRNG (SplitMix64); good enough for fake data.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`; `n` must be non-zero.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+
+    fn uuid(&mut self) -> String {
+        let bytes = (u128::from(self.next()) << 64 | u128::from(self.next())).to_be_bytes();
+        uuid::Builder::from_random_bytes(bytes)
+            .into_uuid()
+            .to_string()
+    }
+}
+
+const WORDS: &[&str] = &[
+    "the",
+    "parser",
+    "returns",
+    "an",
+    "error",
+    "when",
+    "input",
+    "is",
+    "empty",
+    "we",
+    "should",
+    "handle",
+    "this",
+    "case",
+    "before",
+    "calling",
+    "into",
+    "buffer",
+    "thread",
+    "lock",
+    "async",
+    "task",
+    "deadlock",
+    "test",
+    "fails",
+    "on",
+    "ci",
+    "but",
+    "passes",
+    "locally",
+    "refactor",
+    "module",
+    "trait",
+    "struct",
+    "field",
+    "lifetime",
+    "borrow",
+    "checker",
+    "complains",
+    "about",
+    "mutable",
+    "reference",
+    "cache",
+    "invalidation",
+    "index",
+    "query",
+    "is",
+    "slow",
+    "add",
+    "a",
+    "benchmark",
+    "for",
+    "it",
+    "config",
+    "file",
+    "path",
+    "not",
+    "found",
+    "release",
+    "build",
+];
+
+const TOOLS: &[&str] = &[
+    "read_file",
+    "grep",
+    "edit_file",
+    "terminal",
+    "list_directory",
+    "find_path",
+    "diagnostics",
+    "fetch",
+];
+
+const FILES: &[&str] = &[
+    "src/main.rs",
+    "src/lib.rs",
+    "src/parser.rs",
+    "src/db/mod.rs",
+    "tests/integration.rs",
+    "Cargo.toml",
+    "README.md",
+];
+
+const MODELS: &[(&str, &str)] = &[
+    ("anthropic", "claude-sonnet-4"),
+    ("anthropic", "claude-opus-4"),
+    ("openai", "gpt-4o"),
+    ("google", "gemini-2.5-pro"),
+    ("ollama", "qwen2.5-coder"),
+];
+
+struct Generator<'a> {
+    options: &'a GenerateOptions,
+    rng: Rng,
+    /// Each thread is a little older than the previous one.
+    clock: DateTime<Utc>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(options: &'a GenerateOptions) -> Self {
+        Self {
+            options,
+            rng: Rng(options.seed),
+            clock: Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap(),
+        }
+    }
+
+    fn tick(&mut self) -> DateTime<Utc> {
+        self.clock -= Duration::minutes(30 + self.rng.below(24 * 60) as i64);
+        self.clock
+    }
+
+    fn sentence(&mut self) -> String {
+        let len = 4 + self.rng.below(12);
+        let mut words: Vec<&str> = (0..len).map(|_| *self.rng.pick(WORDS)).collect();
+        let first = words[0].to_string();
+        let mut capitalized = first[..1].to_uppercase();
+        capitalized.push_str(&first[1..]);
+        words[0] = &capitalized;
+        format!("{}.", words.join(" "))
+    }
+
+    fn paragraph(&mut self) -> String {
+        let n = 1 + self.rng.below(4);
+        (0..n)
+            .map(|_| self.sentence())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn title(&mut self) -> String {
+        let sentence = self.sentence();
+        let words: Vec<&str> = sentence.trim_end_matches('.').split(' ').take(6).collect();
+        words.join(" ")
+    }
+
+    fn code_block(&mut self) -> String {
+        let name = self.rng.pick(WORDS).to_string();
+        format!(
+            "```rust\nfn {}() -> Result<()> {{\n    // {}\n    Ok(())\n}}\n```",
+            name,
+            self.sentence()
+        )
+    }
+
+    /// Number of messages for one thread, spread around `--messages`.
+    fn message_count(&mut self) -> usize {
+        let avg = self.options.messages.max(1);
+        avg / 2 + self.rng.below(avg + 1)
+    }
+
+    fn image(&mut self) -> LanguageModelImage {
+        let rgb = [
+            self.rng.next() as u8,
+            self.rng.next() as u8,
+            self.rng.next() as u8,
+        ];
+        let png = solid_png(64, 48, rgb);
+        LanguageModelImage {
+            source: base64::engine::general_purpose::STANDARD.encode(png),
+            size: Some(crate::importer::ImageSize {
+                width: 64,
+                height: 48,
+            }),
+        }
+    }
+
+    fn mention(&mut self) -> UserMessageContent {
+        let file = PathBuf::from("/home/dev/project").join(self.rng.pick(FILES));
+        let start = self.rng.below(200) as u32;
+        let uri = match self.rng.below(10) {
+            0 => MentionUri::File {
+                abs_path: file.clone(),
+            },
+            1 => MentionUri::Directory {
+                abs_path: file.parent().unwrap().to_path_buf(),
+            },
+            2 => MentionUri::Symbol {
+                abs_path: file.clone(),
+                name: self.rng.pick(WORDS).to_string(),
+                line_range: start..=start + 10,
+            },
+            3 => MentionUri::Selection {
+                abs_path: Some(file.clone()),
+                line_range: start..=start + 3,
+            },
+            4 => MentionUri::Fetch {
+                url: "https://docs.rs/serde/latest/serde/".parse().unwrap(),
+            },
+            5 => MentionUri::Thread {
+                id: SessionId::new(self.rng.uuid()),
+                name: self.title(),
+            },
+            6 => MentionUri::Rule {
+                id: self.rng.uuid(),
+                name: "Project conventions".into(),
+            },
+            7 => MentionUri::Diagnostics {
+                include_errors: true,
+                include_warnings: self.rng.chance(50),
+            },
+            8 => MentionUri::TerminalSelection { line_count: 3 },
+            _ => MentionUri::TextThread {
+                path: PathBuf::from("/home/dev/.config/zed/conversations/notes.zed.json"),
+                name: self.title(),
+            },
+        };
+        let content = match &uri {
+            MentionUri::File { .. } | MentionUri::Symbol { .. } | MentionUri::Selection { .. } => {
+                self.code_block()
+            }
+            _ => self.paragraph(),
+        };
+        UserMessageContent::Mention { uri, content }
+    }
+
+    fn agent_message(&mut self) -> AgentMessage {
+        let mut content = Vec::new();
+        let mut tool_results = BTreeMap::new();
+        if self.rng.chance(30) {
+            content.push(AgentMessageContent::Thinking {
+                text: self.paragraph(),
+                signature: Some(self.rng.uuid()),
+            });
+        }
+        if self.rng.chance(5) {
+            content.push(AgentMessageContent::RedactedThinking(self.rng.uuid()));
+        }
+        content.push(AgentMessageContent::Text(self.paragraph()));
+        for _ in 0..self.options.tool_calls {
+            let id = format!("toolu_{}", &self.rng.uuid()[..8]);
+            let name = self.rng.pick(TOOLS).to_string();
+            let input = serde_json::json!({ "path": self.rng.pick(FILES) });
+            content.push(AgentMessageContent::ToolUse(LanguageModelToolUse {
+                id: id.clone().into(),
+                name: name.clone(),
+                raw_input: input.to_string(),
+                input,
+                is_input_complete: true,
+                thought_signature: None,
+            }));
+            let is_error = self.rng.chance(10);
+            tool_results.insert(
+                id.clone(),
+                LanguageModelToolResult {
+                    tool_use_id: id.into(),
+                    tool_name: name,
+                    is_error,
+                    content: LanguageModelToolResultContent::Text(self.paragraph()),
+                    output: None,
+                },
+            );
+        }
+        if self.rng.chance(50) {
+            content.push(AgentMessageContent::Text(self.code_block()));
+        }
+        AgentMessage {
+            content,
+            tool_results,
+            reasoning_details: None,
+        }
+    }
+
+    fn agent_thread(&mut self, parent: Option<String>) -> Result<Row> {
+        let id = self.rng.uuid();
+        let updated_at = self.tick();
+        let title = match parent {
+            Some(_) => format!("Subagent: {}", self.title()),
+            None => self.title(),
+        };
+
+        let n = self.message_count();
+        let mut messages = Vec::with_capacity(n);
+        let mut request_token_usage = HashMap::new();
+        let mut total = TokenUsage::default();
+        for i in 0..n {
+            if i % 2 == 1 {
+                messages.push(Message::Agent(self.agent_message()));
+                continue;
+            }
+            if i > 0 && self.rng.chance(5) {
+                messages.push(Message::Resume);
+                continue;
+            }
+            let user_id = self.rng.uuid();
+            let usage = TokenUsage {
+                input_tokens: 500 + self.rng.below(20_000) as u64,
+                output_tokens: 50 + self.rng.below(2_000) as u64,
+                ..Default::default()
+            };
+            total.input_tokens += usage.input_tokens;
+            total.output_tokens += usage.output_tokens;
+            request_token_usage.insert(user_id.clone(), usage);
+            messages.push(Message::User(UserMessage {
+                id: user_id.into(),
+                content: vec![UserMessageContent::Text(self.paragraph())],
+            }));
+        }
+
+        let user_indices: Vec<usize> = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| matches!(m, Message::User(_)))
+            .map(|(i, _)| i)
+            .collect();
+        if !user_indices.is_empty() {
+            for _ in 0..self.options.mentions {
+                let mention = self.mention();
+                self.push_user_content(&mut messages, &user_indices, mention);
+            }
+            for _ in 0..self.options.images {
+                let image = UserMessageContent::Image(self.image());
+                self.push_user_content(&mut messages, &user_indices, image);
+            }
+        }
+
+        let (provider, model) = *self.rng.pick(MODELS);
+        let thread = DbThread {
+            title: title.clone(),
+            messages,
+            updated_at,
+            detailed_summary: self.rng.chance(20).then(|| self.paragraph()),
+            initial_project_snapshot: Some(self.project_snapshot(updated_at)),
+            cumulative_token_usage: total,
+            request_token_usage,
+            model: Some(SerializedLanguageModel {
+                provider: provider.into(),
+                model: model.into(),
+            }),
+            profile: None,
+            imported: false,
+            subagent_context: parent.as_ref().map(|p| SubagentContext {
+                parent_thread_id: SessionId::new(p.clone()),
+                depth: 1,
+            }),
+        };
+        Ok(Row {
+            id,
+            parent_id: parent,
+            summary: title,
+            updated_at,
+            json: thread.to_db_json()?,
+        })
+    }
+
+    fn push_user_content(
+        &mut self,
+        messages: &mut [Message],
+        user_indices: &[usize],
+        content: UserMessageContent,
+    ) {
+        let i = *self.rng.pick(user_indices);
+        if let Message::User(user) = &mut messages[i] {
+            user.content.push(content);
+        }
+    }
+
+    fn project_snapshot(&mut self, at: DateTime<Utc>) -> ProjectSnapshot {
+        ProjectSnapshot {
+            worktree_snapshots: vec![TelemetryWorktreeSnapshot {
+                worktree_path: "/home/dev/project".into(),
+                git_state: Some(GitState {
+                    remote_url: Some("git@github.com:example/project.git".into()),
+                    head_sha: Some(format!("{:016x}", self.rng.next())),
+                    current_branch: Some("main".into()),
+                    diff: None,
+                }),
+            }],
+            timestamp: at - Duration::minutes(5),
+        }
+    }
+
+    fn legacy_thread(&mut self, version: &str) -> Result<Row> {
+        let id = self.rng.uuid();
+        let updated_at = self.tick() - Duration::days(365);
+        let title = self.title();
+
+        let n = self.message_count();
+        let mut messages = Vec::with_capacity(n);
+        for i in 0..n {
+            let role = if i % 2 == 0 {
+                Role::User
+            } else {
+                Role::Assistant
+            };
+            let mut segments = Vec::new();
+            let mut tool_uses = Vec::new();
+            let mut tool_results = Vec::new();
+            if role == Role::Assistant {
+                if self.rng.chance(30) {
+                    segments.push(SerializedMessageSegment::Thinking {
+                        text: self.paragraph(),
+                        signature: None,
+                    });
+                }
+                if self.rng.chance(5) {
+                    segments.push(SerializedMessageSegment::RedactedThinking {
+                        data: self.rng.uuid(),
+                    });
+                }
+                for _ in 0..self.options.tool_calls {
+                    let tool_id = format!("toolu_{}", &self.rng.uuid()[..8]);
+                    tool_uses.push(SerializedToolUse {
+                        id: tool_id.clone().into(),
+                        name: self.rng.pick(TOOLS).to_string(),
+                        input: serde_json::json!({ "path": self.rng.pick(FILES) }),
+                    });
+                    tool_results.push(SerializedToolResult {
+                        tool_use_id: tool_id.into(),
+                        is_error: false,
+                        content: LanguageModelToolResultContent::Text(self.paragraph()),
+                        output: None,
+                    });
+                }
+            }
+            segments.push(SerializedMessageSegment::Text {
+                text: self.paragraph(),
+            });
+            let creases = if role == Role::User && self.rng.chance(20) {
+                vec![SerializedCrease {
+                    start: 0,
+                    end: 4,
+                    icon_path: "icons/file_icons/rust.svg".into(),
+                    label: self.rng.pick(FILES).to_string(),
+                }]
+            } else {
+                Vec::new()
+            };
+            messages.push(SerializedMessage {
+                id: MessageId(i),
+                role,
+                segments,
+                tool_uses,
+                tool_results,
+                context: String::new(),
+                creases,
+                is_hidden: false,
+            });
+        }
+
+        let (provider, model) = *self.rng.pick(MODELS);
+        let thread = SerializedThread {
+            version: version.into(),
+            summary: title.clone(),
+            updated_at,
+            messages,
+            initial_project_snapshot: None,
+            cumulative_token_usage: TokenUsage::default(),
+            request_token_usage: Vec::new(),
+            detailed_summary_state: DetailedSummaryState::NotGenerated,
+            model: Some(SerializedLanguageModel {
+                provider: provider.into(),
+                model: model.into(),
+            }),
+            tool_use_limit_reached: false,
+            profile: None,
+        };
+        Ok(Row {
+            id,
+            parent_id: None,
+            summary: title,
+            updated_at,
+            json: serde_json::to_vec(&thread)?,
+        })
+    }
+}
+
+/// A `width`×`height` PNG of one colour.
+pub fn solid_png(width: u32, height: u32, rgb: [u8; 3]) -> Vec<u8> {
+    let mut png = Vec::new();
+    RgbImage::from_pixel(width, height, Rgb(rgb))
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("encoding a PNG in memory");
+    png
+}
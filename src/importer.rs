@@ -31,13 +31,229 @@
 /// - `crates/agent/src/agent.rs`                   – `ProjectSnapshot`
 /// - `crates/project/src/telemetry_snapshot.rs`    – `TelemetryWorktreeSnapshot`, `GitState`
 /// - `crates/agent_client_protocol` (external crate, crates.io) – `SessionId`
-use std::{collections::HashMap, ops::RangeInclusive, path::PathBuf};
+/// - `crates/assistant_context/src/assistant_context.rs` – `SavedContext`, `SavedMessage`, `MessageMetadata` (text threads, stored as `conversations/*.zed.json` files)
+use std::{collections::HashMap, marker::PhantomData, ops::RangeInclusive, path::PathBuf};
 
 use chrono::{DateTime, Utc};
+use serde::de::value::StrDeserializer;
+use serde::de::{self, DeserializeSeed, EnumAccess, MapAccess, VariantAccess, Visitor};
 use serde::{Deserialize, Serialize};
 use url::Url;
 use uuid::Uuid;
 
+// ---------------------------------------------------------------------------
+// Forward compatibility
+// ---------------------------------------------------------------------------
+
+/// Implements `Serialize` and `Deserialize` for an enum whose derives use
+/// `#[serde(remote = "Self")]` and which has a `#[serde(skip)] Unknown(serde_json::Value)`
+/// variant.
+///
+/// A variant this crate does not know (typically added by a newer Zed) deserializes
+/// to `Unknown` holding the raw JSON instead of failing the whole thread, and
+/// serializes back unchanged. Only the outer tag is checked against the derived
+/// variants: a known variant with a malformed payload, or with an unknown variant of
+/// another enum nested inside, is still an error.
+macro_rules! tolerant_enum {
+    ($ty:ident) => {
+        impl Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                match self {
+                    $ty::Unknown(value) => value.serialize(serializer),
+                    known => $ty::serialize(known, serializer),
+                }
+            }
+        }
+
+        impl TolerantEnum for $ty {
+            fn derived<'de, D: serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                $ty::deserialize(deserializer)
+            }
+
+            fn unknown(value: serde_json::Value) -> Self {
+                $ty::Unknown(value)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let mut known = &[][..];
+                let _ = $ty::deserialize(VariantNames(&mut known));
+                deserializer.deserialize_any(Tolerant::<$ty> {
+                    known,
+                    ty: PhantomData,
+                })
+            }
+        }
+    };
+}
+
+/// An enum given its `Deserialize` by [`tolerant_enum!`].
+trait TolerantEnum: Sized {
+    /// The derived (`remote = "Self"`) deserializer, which fails on unknown variants.
+    fn derived<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+    fn unknown(value: serde_json::Value) -> Self;
+}
+
+/// Reads the tag of an externally tagged enum, then hands a known variant to the
+/// derived deserializer in the same pass, or keeps an unknown one whole as a
+/// `Value`. Nothing is buffered, so nested tolerant enums cost no extra copies.
+struct Tolerant<T> {
+    known: &'static [&'static str],
+    ty: PhantomData<T>,
+}
+
+impl<'de, T: TolerantEnum> Visitor<'de> for Tolerant<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an externally tagged enum")
+    }
+
+    fn visit_str<E: de::Error>(self, tag: &str) -> Result<T, E> {
+        if self.known.contains(&tag) {
+            T::derived(StrDeserializer::new(tag))
+        } else {
+            Ok(T::unknown(serde_json::Value::String(tag.to_string())))
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<T, A::Error> {
+        let Some(tag) = map.next_key::<String>()? else {
+            return Err(de::Error::invalid_length(0, &self));
+        };
+        let value = if self.known.contains(&tag.as_str()) {
+            T::derived(KnownVariant {
+                tag: &tag,
+                map: &mut map,
+            })?
+        } else {
+            let payload: serde_json::Value = map.next_value()?;
+            T::unknown(serde_json::Value::Object(
+                [(tag, payload)].into_iter().collect(),
+            ))
+        };
+        if map.next_key::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(2, &self));
+        }
+        Ok(value)
+    }
+}
+
+/// The rest of a `{"Tag": payload}` map whose tag was already read, as the enum
+/// deserializer the derived impl expects.
+struct KnownVariant<'a, A> {
+    tag: &'a str,
+    map: &'a mut A,
+}
+
+impl<'de, A: MapAccess<'de>> de::Deserializer<'de> for KnownVariant<'_, A> {
+    type Error = A::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, A::Error> {
+        visitor.visit_enum(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+        struct enum identifier ignored_any
+    }
+}
+
+impl<'de, A: MapAccess<'de>> EnumAccess<'de> for KnownVariant<'_, A> {
+    type Error = A::Error;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Self), A::Error> {
+        let variant = seed.deserialize(StrDeserializer::new(self.tag))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, A: MapAccess<'de>> VariantAccess<'de> for KnownVariant<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.map.next_value()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        self.map.next_value_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(TupleSeed(len, visitor))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.map.next_value_seed(StructSeed(fields, visitor))
+    }
+}
+
+struct TupleSeed<V>(usize, V);
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for TupleSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+        d.deserialize_tuple(self.0, self.1)
+    }
+}
+
+struct StructSeed<V>(&'static [&'static str], V);
+
+impl<'de, V: Visitor<'de>> DeserializeSeed<'de> for StructSeed<V> {
+    type Value = V::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+        d.deserialize_struct("", self.0, self.1)
+    }
+}
+/// A deserializer that only records the variant names a derived enum passes to
+/// `deserialize_enum`, then fails.
+struct VariantNames<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> serde::Deserializer<'de> for VariantNames<'_> {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("not an enum"))
+    }
+
+    fn deserialize_enum<V: serde::de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = variants;
+        Err(serde::de::Error::custom("variant names recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes
+        byte_buf option unit unit_struct newtype_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+/// Name of the variant an unrecognized externally tagged enum value carries: the
+/// single key of an object, or the string itself for a unit variant.
+pub fn variant_name(value: &serde_json::Value) -> Option<&str> {
+    match value {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Object(map) if map.len() == 1 => map.keys().next().map(String::as_str),
+        _ => None,
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Primitive / ID types
 // ---------------------------------------------------------------------------
@@ -78,6 +294,12 @@ impl Default for UserMessageId {
     }
 }
 
+impl<T: Into<String>> From<T> for UserMessageId {
+    fn from(value: T) -> Self {
+        Self(value.into())
+    }
+}
+
 /// ID for a tool use invocation.
 ///
 /// Source: `crates/language_model/src/language_model.rs`
@@ -350,7 +572,8 @@ pub struct LanguageModelToolResult {
 /// A URI that identifies context attached to a user message (@-mention).
 ///
 /// Source: `crates/acp_thread/src/mention.rs`
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum MentionUri {
     /// An absolute path to a file on disk.
     File { abs_path: PathBuf },
@@ -397,8 +620,14 @@ pub enum MentionUri {
 
     /// A selection from a terminal buffer.
     TerminalSelection { line_count: u32 },
+
+    /// A mention kind this exporter does not know, kept as raw JSON.
+    #[serde(skip)]
+    Unknown(serde_json::Value),
 }
 
+tolerant_enum!(MentionUri);
+
 fn default_include_errors() -> bool {
     true
 }
@@ -411,6 +640,7 @@ fn default_include_errors() -> bool {
 ///
 /// Source: `crates/agent/src/thread.rs`
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum UserMessageContent {
     /// Plain text typed by the user.
     Text(String),
@@ -420,12 +650,19 @@ pub enum UserMessageContent {
 
     /// An image pasted or dropped into the input.
     Image(LanguageModelImage),
+
+    /// A content kind this exporter does not know, kept as raw JSON.
+    #[serde(skip)]
+    Unknown(serde_json::Value),
 }
 
+tolerant_enum!(UserMessageContent);
+
 /// The content of an agent (assistant) message.
 ///
 /// Source: `crates/agent/src/thread.rs`
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum AgentMessageContent {
     /// A prose text segment.
     Text(String),
@@ -442,8 +679,14 @@ pub enum AgentMessageContent {
 
     /// A tool invocation.
     ToolUse(LanguageModelToolUse),
+
+    /// A content kind this exporter does not know, kept as raw JSON.
+    #[serde(skip)]
+    Unknown(serde_json::Value),
 }
 
+tolerant_enum!(AgentMessageContent);
+
 // ---------------------------------------------------------------------------
 // Messages
 // ---------------------------------------------------------------------------
@@ -480,12 +723,19 @@ pub struct AgentMessage {
 ///
 /// Source: `crates/agent/src/thread.rs`
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum Message {
     User(UserMessage),
     Agent(AgentMessage),
     Resume,
+
+    /// A message kind this exporter does not know, kept as raw JSON.
+    #[serde(skip)]
+    Unknown(serde_json::Value),
 }
 
+tolerant_enum!(Message);
+
 // ---------------------------------------------------------------------------
 // Subagent context
 // ---------------------------------------------------------------------------
@@ -597,6 +847,19 @@ pub struct DbThread {
     pub subagent_context: Option<SubagentContext>,
 }
 
+impl DbThread {
+    pub const VERSION: &'static str = "0.3.0";
+
+    /// Serialize the way `save_thread_sync` does, with the `version` field injected.
+    pub fn to_db_json(&self) -> serde_json::Result<Vec<u8>> {
+        let mut value = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(ref mut obj) = value {
+            obj.insert("version".into(), Self::VERSION.into());
+        }
+        serde_json::to_vec(&value)
+    }
+}
+
 /// Lightweight row returned by `SELECT id, parent_id, summary, updated_at FROM threads`.
 ///
 /// Source: `crates/agent/src/db.rs`
@@ -610,6 +873,16 @@ pub struct DbThreadMetadata {
     pub updated_at: DateTime<Utc>,
 }
 
+/// DDL for the `threads` table, as created by Zed (see the module docs).
+pub const THREADS_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS threads (
+    id TEXT PRIMARY KEY,
+    parent_id TEXT,
+    summary TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    data_type TEXT NOT NULL,
+    data BLOB NOT NULL
+)";
+
 /// The data type stored in the `data_type` SQLite column.
 ///
 /// Source: `crates/agent/src/db.rs`
@@ -636,6 +909,58 @@ pub struct SharedThread {
     pub version: String,
 }
 
+impl SharedThread {
+    pub const VERSION: &'static str = "1.0.0";
+
+    /// Build a shareable document from a stored thread, dropping local-only state
+    /// (project snapshot, token usage, profile, subagent context).
+    pub fn from_db_thread(thread: &DbThread) -> Self {
+        Self {
+            title: thread.title.clone(),
+            messages: thread.messages.clone(),
+            updated_at: thread.updated_at,
+            model: thread.model.clone(),
+            version: Self::VERSION.to_string(),
+        }
+    }
+
+    /// Convert back into a `DbThread`, marked as `imported` the way Zed does on import.
+    pub fn into_db_thread(self) -> DbThread {
+        DbThread {
+            title: self.title,
+            messages: self.messages,
+            updated_at: self.updated_at,
+            detailed_summary: None,
+            initial_project_snapshot: None,
+            cumulative_token_usage: TokenUsage::default(),
+            request_token_usage: HashMap::new(),
+            model: self.model,
+            profile: None,
+            imported: true,
+            subagent_context: None,
+        }
+    }
+
+    /// Serialize to zstd-compressed JSON (compression level 3), matching Zed's share payload.
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let json = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        zstd::encode_all(json.as_slice(), 3)
+    }
+
+    /// Parse a zstd-compressed (or plain JSON) share payload.
+    pub fn from_bytes(data: &[u8]) -> std::io::Result<Self> {
+        let json = if data.starts_with(&ZSTD_MAGIC) {
+            zstd::decode_all(data)?
+        } else {
+            data.to_vec()
+        };
+        serde_json::from_slice(&json).map_err(std::io::Error::other)
+    }
+}
+
+/// Magic number at the start of every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
 // ---------------------------------------------------------------------------
 // Legacy formats (v0.1.0 / v0.2.0) – needed to deserialize old threads
 // ---------------------------------------------------------------------------
@@ -764,3 +1089,76 @@ pub struct SerializedThread {
     #[serde(default)]
     pub profile: Option<AgentProfileId>,
 }
+
+// ---------------------------------------------------------------------------
+// Text threads
+// ---------------------------------------------------------------------------
+
+/// Metadata of a text thread message. Only the role is read; the status, Lamport
+/// timestamp and cache state are editor bookkeeping.
+///
+/// Source: `crates/assistant_context/src/assistant_context.rs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageMetadata {
+    pub role: Role,
+}
+
+/// Where a message starts in the text thread's buffer. Version 0.1.0 kept the
+/// metadata in [`SavedContext::message_metadata`] instead, keyed by `id`.
+///
+/// Source: `crates/assistant_context/src/assistant_context.rs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedMessage {
+    /// A Lamport timestamp object, or a plain counter in 0.1.0.
+    pub id: serde_json::Value,
+    /// Byte offset into [`SavedContext::text`].
+    pub start: usize,
+    #[serde(default)]
+    pub metadata: Option<MessageMetadata>,
+}
+
+/// A text thread: the editor-based assistant panel's conversation, one buffer of
+/// text split into messages by offset. Stored as a file rather than in `threads.db`.
+///
+/// Versions 0.1.0 to 0.4.0 differ only in fields not read here.
+///
+/// Source: `crates/assistant_context/src/assistant_context.rs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedContext {
+    /// A UUID string; absent before 0.2.0.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Always `"context"`.
+    pub zed: String,
+    pub version: String,
+    pub text: String,
+    pub messages: Vec<SavedMessage>,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub message_metadata: HashMap<String, MessageMetadata>,
+}
+
+impl SavedContext {
+    /// Role and text of each message, in order. The newline separating a message
+    /// from the next is not part of it.
+    pub fn message_texts(&self) -> Vec<(Role, &str)> {
+        let mut messages = Vec::new();
+        for (i, message) in self.messages.iter().enumerate() {
+            let end = self
+                .messages
+                .get(i + 1)
+                .map_or(self.text.len(), |next| next.start);
+            let role = message.metadata.as_ref().map(|m| m.role).or_else(|| {
+                let key = match &message.id {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                self.message_metadata.get(&key).map(|m| m.role)
+            });
+            let text = self.text.get(message.start..end).unwrap_or_default();
+            messages.push((role.unwrap_or(Role::User), text.trim_end_matches('\n')));
+        }
+        messages
+    }
+}
@@ -1,21 +1,44 @@
+mod anonymize;
+mod archive;
+mod assets;
+mod doctor;
+mod edits;
+mod engine;
+mod generate;
 mod importer;
+mod manifest;
+#[cfg(not(feature = "sequential"))]
 mod parallel;
+mod plan;
 mod renderer;
+mod report;
+mod restore;
+mod rules;
+mod schema;
 #[cfg(feature = "sequential")]
 mod sequential;
+mod share;
+mod text_thread;
 mod utils;
 
-use clap::Parser;
-use eyre::{eyre, Context, Result};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use eyre::{Context, Result, eyre};
 use serde::Deserialize;
 use std::fs;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
 /// Export Zed editor AI chat history to Markdown files.
 /// Up to date with 0.225.9
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_precedence_over_arg = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Directory to export markdown files.
     /// Defaults to ./zed-chat-export if not set in config.
     #[arg(value_name = "TARGET_DIR")]
@@ -23,12 +46,12 @@ struct Cli {
 
     /// Path to Zed SQLite DB (threads.db).
     /// Auto-detected if omitted.
-    #[arg(long, value_name = "PATH")]
+    #[arg(long, value_name = "PATH", global = true)]
     db: Option<PathBuf>,
 
     /// Path to a specific configuration file.
     /// Defaults to $XDG_CONFIG_HOME/zed-export/config.toml
-    #[arg(long, value_name = "PATH")]
+    #[arg(long, value_name = "PATH", global = true)]
     config: Option<PathBuf>,
 
     /// Comma-separated tags to add to frontmatter (e.g. "zed,llm").
@@ -50,6 +73,183 @@ struct Cli {
     /// Include @-mention context blocks (file, symbol, selection, etc.) in output.
     #[arg(long)]
     include_context: bool,
+
+    /// Also store each thread's raw JSON losslessly under TARGET_DIR/raw/
+    /// (zstd-compressed unless --archive-raw=json is given).
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "zstd"
+    )]
+    archive_raw: Option<archive::RawFormat>,
+
+    /// What to do with exported files you have edited since they were written.
+    /// Notes between `<!-- zed-chat-export:notes -->` markers are always kept.
+    #[arg(long, value_name = "POLICY")]
+    on_edit: Option<edits::EditPolicy>,
+
+    /// Remove exported files of threads that no longer exist in the database.
+    #[arg(long)]
+    prune: bool,
+
+    /// Show what would be created, updated, renamed or pruned without writing anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// With --dry-run, print the plan as JSON on stdout.
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+
+    /// Write a machine-readable run report (per-thread outcome, errors, timings).
+    #[arg(long, value_name = "FORMAT")]
+    report: Option<report::ReportFormat>,
+
+    /// File to write the --report to. Defaults to stdout.
+    #[arg(long, value_name = "PATH", requires = "report")]
+    report_file: Option<PathBuf>,
+
+    /// Stop at the first thread that fails to export.
+    #[arg(long, conflicts_with = "max_errors")]
+    fail_fast: bool,
+
+    /// Stop once N threads have failed to export.
+    #[arg(long, value_name = "N")]
+    max_errors: Option<NonZeroUsize>,
+
+    /// Read threads whose JSON version this exporter does not know with this schema,
+    /// e.g. after a Zed update that bumped the version without breaking the format.
+    #[arg(long, value_name = "SCHEMA")]
+    zed_schema: Option<schema::SchemaName>,
+
+    /// Downscale images whose longer side exceeds PX pixels.
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    image_max_dimension: Option<u32>,
+
+    /// Convert PNG images to this format to save space (kept as PNG when that is smaller).
+    #[arg(long, value_name = "FORMAT")]
+    image_format: Option<assets::ImageFormat>,
+
+    /// Embed images up to KIB kibibytes (default 512, or --inline-images=KIB) in the
+    /// markdown as data URIs, for single-file exports. Larger images are still
+    /// written to assets/.
+    #[arg(
+        long,
+        value_name = "KIB",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "512"
+    )]
+    inline_images: Option<u32>,
+
+    /// Write the content of @-mentioned files and fetched URLs to
+    /// assets/<file>/<n>-<name> and link it from the message.
+    #[arg(long)]
+    mention_assets: bool,
+
+    /// Path to Zed's rules library (the prompts-library-db.0.mdb directory), exported
+    /// to TARGET_DIR/rules/. Auto-detected if omitted.
+    #[arg(long, value_name = "PATH")]
+    rules_db: Option<PathBuf>,
+
+    /// Directory of Zed's text threads (conversations/*.zed.json), exported along
+    /// with the database. Auto-detected if omitted.
+    #[arg(long, value_name = "PATH")]
+    text_threads: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a thread as a zstd-compressed Zed SharedThread file that can be imported into Zed.
+    Share {
+        /// Thread id, or a unique prefix of it (e.g. the 8 chars starting an exported filename).
+        #[arg(value_name = "THREAD_ID")]
+        id: String,
+
+        /// Output file. Defaults to ./<THREAD_ID>.zed-thread
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Render a SharedThread file to markdown with the normal renderer.
+    RenderShared {
+        /// SharedThread file (zstd-compressed or plain JSON).
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+
+        /// Directory to write the markdown file into.
+        /// Defaults to the configured target dir, or ./zed-chat-export.
+        #[arg(value_name = "TARGET_DIR")]
+        target_dir: Option<PathBuf>,
+
+        /// Comma-separated tags to add to frontmatter (e.g. "zed,llm").
+        #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+        tags: Option<Vec<String>>,
+
+        /// Include @-mention context blocks in output.
+        #[arg(long)]
+        include_context: bool,
+    },
+
+    /// Rebuild a Zed threads.db from exported markdown, so conversations can be reopened in Zed.
+    Restore {
+        /// Directory containing exported markdown files.
+        /// Defaults to the configured target dir, or ./zed-chat-export.
+        #[arg(value_name = "SOURCE_DIR")]
+        source_dir: Option<PathBuf>,
+
+        /// Path of the threads.db to create.
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Overwrite the output database if it exists.
+        #[arg(short, long)]
+        force: bool,
+
+        /// Print each restored thread.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Check that every thread in the database can be read, and explain what fails.
+    Doctor {
+        /// List every affected thread id, not just the first.
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Read threads whose JSON version is unknown with this schema, as the export
+        /// would with the same option.
+        #[arg(long, value_name = "SCHEMA")]
+        zed_schema: Option<schema::SchemaName>,
+    },
+
+    /// Write a fake threads.db with synthetic conversations, for benchmarks, tests and
+    /// reproducing bugs without sharing real chats.
+    Generate {
+        /// Path of the threads.db to create.
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Overwrite the output database if it exists.
+        #[arg(short, long)]
+        force: bool,
+
+        #[command(flatten)]
+        options: generate::GenerateOptions,
+    },
+
+    /// Copy threads.db with all text, paths, URLs and images replaced by placeholders
+    /// of the same shape, so it can be attached to a bug report.
+    Anonymize {
+        /// Path of the anonymized threads.db to create.
+        #[arg(short, long, value_name = "PATH")]
+        output: PathBuf,
+
+        /// Overwrite the output database if it exists.
+        #[arg(short, long)]
+        force: bool,
+    },
 }
 
 #[derive(Deserialize, Default)]
@@ -57,10 +257,45 @@ struct FileConfig {
     target_dir: Option<PathBuf>,
     db_path: Option<PathBuf>,
     tags: Option<Vec<String>>,
+    archive_raw: Option<archive::RawFormat>,
+    on_edit: Option<edits::EditPolicy>,
+    prune: Option<bool>,
+    zed_schema: Option<schema::SchemaName>,
+    image_max_dimension: Option<u32>,
+    image_format: Option<assets::ImageFormat>,
+    inline_images: Option<u32>,
+    mention_assets: Option<bool>,
+    rules_db: Option<PathBuf>,
+    text_threads_dir: Option<PathBuf>,
+}
+
+/// Zed's data directory: `~/Library/Application Support/Zed` on macOS,
+/// `%LOCALAPPDATA%\Zed` on Windows and `$XDG_DATA_HOME/zed` elsewhere.
+fn zed_data_dir() -> Option<PathBuf> {
+    let name = if cfg!(any(target_os = "macos", target_os = "windows")) {
+        "Zed"
+    } else {
+        "zed"
+    };
+    dirs::data_local_dir().map(|d| d.join(name))
 }
 
 fn default_db_path() -> Option<PathBuf> {
-    dirs::data_dir().map(|d| d.join("Zed/threads/threads.db"))
+    zed_data_dir().map(|d| d.join("threads/threads.db"))
+}
+
+fn default_rules_db_path() -> Option<PathBuf> {
+    zed_data_dir().map(|d| d.join("prompts/prompts-library-db.0.mdb"))
+}
+
+fn default_text_threads_dir() -> Option<PathBuf> {
+    // Zed keeps text threads under ~/.config/zed on macOS, and in its data
+    // directory elsewhere.
+    if cfg!(target_os = "macos") {
+        dirs::home_dir().map(|d| d.join(".config/zed/conversations"))
+    } else {
+        zed_data_dir().map(|d| d.join("conversations"))
+    }
 }
 
 fn load_file_config(explicit_path: Option<&Path>) -> Result<FileConfig> {
@@ -87,19 +322,9 @@ fn load_file_config(explicit_path: Option<&Path>) -> Result<FileConfig> {
     }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-
-    let file_cfg = load_file_config(cli.config.as_deref())?;
-
-    let target_dir = cli
-        .target_dir
-        .or(file_cfg.target_dir)
-        .unwrap_or_else(|| PathBuf::from("zed-chat-export"));
-
-    let db_path = cli
-        .db
-        .or(file_cfg.db_path)
+fn resolve_db_path(cli_db: Option<PathBuf>, file_cfg_db: Option<PathBuf>) -> Result<PathBuf> {
+    let db_path = cli_db
+        .or(file_cfg_db)
         .or_else(default_db_path)
         .ok_or_else(|| {
             eyre!("Could not determine database path.\nUse --db to specify manually, or set db_path in config.toml.")
@@ -111,9 +336,180 @@ fn main() -> Result<()> {
             db_path.display()
         ));
     }
+    Ok(db_path)
+}
+
+/// Parse the command line. A subcommand name is always read as the subcommand, even
+/// after `--db` or `--config`, so export options given with one are an error rather
+/// than the subcommand being taken for TARGET_DIR.
+fn parse_cli() -> Cli {
+    let mut command = Cli::command();
+    let matches = command.get_matches_mut();
+    if let Some((name, _)) = matches.subcommand() {
+        let export_arg = command
+            .get_arguments()
+            .filter(|arg| !arg.is_global_set())
+            .find(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+            .map(|arg| match arg.get_long() {
+                Some(long) => format!("--{}", long),
+                None => arg.get_id().as_str().to_uppercase(),
+            });
+        if let Some(arg) = export_arg {
+            command
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!("{} cannot be used with the '{}' subcommand", arg, name),
+                )
+                .exit();
+        }
+    }
+    Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = parse_cli();
+
+    let file_cfg = load_file_config(cli.config.as_deref())?;
+
+    match cli.command {
+        Some(Command::Share { id, output }) => {
+            let db_path = resolve_db_path(cli.db, file_cfg.db_path)?;
+            let written = share::export_shared(&db_path, &id, output)?;
+            eprintln!("Wrote {}", written.display());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::RenderShared {
+            input,
+            target_dir,
+            tags,
+            include_context,
+        }) => {
+            let target_dir = target_dir
+                .or(file_cfg.target_dir)
+                .unwrap_or_else(|| PathBuf::from("zed-chat-export"));
+            let tags = tags.or(file_cfg.tags);
+            let options = renderer::RenderOptions::new(
+                tags.as_deref(),
+                include_context,
+                file_cfg.image_max_dimension,
+                file_cfg.image_format.unwrap_or_default(),
+                file_cfg.inline_images,
+                file_cfg.mention_assets.unwrap_or(false),
+                Arc::new(rules::exported(&target_dir)),
+            );
+            let written = share::render_shared(&input, &target_dir, &options)?;
+            eprintln!("Wrote {}", written.display());
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Restore {
+            source_dir,
+            output,
+            force,
+            verbose,
+        }) => {
+            let source_dir = source_dir
+                .or(file_cfg.target_dir)
+                .unwrap_or_else(|| PathBuf::from("zed-chat-export"));
+            let summary = restore::restore(&source_dir, &output, force, verbose)?;
+            eprintln!(
+                "Done. {} restored ({} from raw archive), {} skipped. Wrote {}",
+                summary.restored,
+                summary.from_archive,
+                summary.skipped,
+                output.display()
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Doctor {
+            verbose,
+            zed_schema,
+        }) => {
+            let db_path = resolve_db_path(cli.db, file_cfg.db_path)?;
+            let diagnosis = doctor::diagnose(&db_path, zed_schema.or(file_cfg.zed_schema))?;
+            doctor::print(&db_path, &diagnosis, verbose);
+            return Ok(if diagnosis.is_healthy() {
+                ExitCode::SUCCESS
+            } else {
+                plan::Outcome::Schema.exit_code()
+            });
+        }
+        Some(Command::Generate {
+            output,
+            force,
+            options,
+        }) => {
+            let summary = generate::generate(&output, &options, force)?;
+            eprintln!(
+                "Wrote {} threads ({} agent, {} subagent, {} legacy; {} json, {} zstd) to {}",
+                summary.total(),
+                summary.agent,
+                summary.subagents,
+                summary.legacy,
+                summary.json_rows,
+                summary.zstd_rows,
+                output.display()
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Anonymize { output, force }) => {
+            let db_path = resolve_db_path(cli.db, file_cfg.db_path)?;
+            let summary = anonymize::anonymize(&db_path, &output, force)?;
+            eprintln!(
+                "Anonymized {} threads ({} skipped) to {}",
+                summary.threads,
+                summary.skipped,
+                output.display()
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    let target_dir = cli
+        .target_dir
+        .or(file_cfg.target_dir)
+        .unwrap_or_else(|| PathBuf::from("zed-chat-export"));
+
+    let db_path = resolve_db_path(cli.db, file_cfg.db_path)?;
 
     let tags = cli.tags.or(file_cfg.tags);
 
+    // An explicitly configured library must exist; the default one is optional.
+    let rules_db = match cli.rules_db.or(file_cfg.rules_db) {
+        Some(path) if !path.exists() => {
+            return Err(eyre!("Rules library not found at: {}", path.display()));
+        }
+        Some(path) => Some(path),
+        None => default_rules_db_path().filter(|p| p.exists()),
+    };
+    let text_threads_dir = match cli.text_threads.or(file_cfg.text_threads_dir) {
+        Some(dir) if !dir.is_dir() => {
+            return Err(eyre!(
+                "Text threads directory not found at: {}",
+                dir.display()
+            ));
+        }
+        Some(dir) => Some(dir),
+        None => default_text_threads_dir().filter(|d| d.is_dir()),
+    };
+    let prune = cli.prune || file_cfg.prune.unwrap_or(false);
+
+    // Rules are archived before the threads, which link to them, but never fail the
+    // export.
+    if let Some(rules_db) = rules_db.filter(|_| !cli.dry_run) {
+        match rules::read_library(&rules_db).and_then(|r| rules::export(&target_dir, &r, prune)) {
+            Ok(summary) if !cli.quiet && summary.written + summary.removed > 0 => eprintln!(
+                "Rules: {} written, {} unchanged, {} removed.",
+                summary.written, summary.unchanged, summary.removed
+            ),
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: rules library not exported: {:#}", e),
+        }
+    }
+    let rules = Arc::new(rules::exported(&target_dir));
+
     let config = utils::ExportConfig {
         target_dir,
         db_path,
@@ -122,11 +518,38 @@ fn main() -> Result<()> {
         verbose: cli.verbose,
         quiet: cli.quiet,
         include_context: cli.include_context,
+        archive_raw: cli.archive_raw.or(file_cfg.archive_raw),
+        on_edit: cli.on_edit.or(file_cfg.on_edit).unwrap_or_default(),
+        dry_run: cli.dry_run,
+        plan_json: cli.json,
+        prune,
+        report: cli.report,
+        report_path: cli.report_file,
+        max_errors: if cli.fail_fast {
+            Some(1)
+        } else {
+            cli.max_errors.map(NonZeroUsize::get)
+        },
+        zed_schema: cli.zed_schema.or(file_cfg.zed_schema),
+        image_max_dimension: cli.image_max_dimension.or(file_cfg.image_max_dimension),
+        image_format: cli
+            .image_format
+            .or(file_cfg.image_format)
+            .unwrap_or_default(),
+        inline_images: cli.inline_images.or(file_cfg.inline_images),
+        mention_assets: cli.mention_assets || file_cfg.mention_assets.unwrap_or(false),
+        text_threads_dir,
+        rules,
     };
 
-    #[cfg(feature = "sequential")]
-    return sequential::execute(config);
+    let result = engine::execute(config);
 
-    #[cfg(not(feature = "sequential"))]
-    parallel::execute(config)
+    match result {
+        Ok(outcome) => Ok(outcome.exit_code()),
+        Err(e) if e.is::<schema::IncompatibleTable>() => {
+            eprintln!("Error: {}", e);
+            Ok(plan::Outcome::Schema.exit_code())
+        }
+        Err(e) => Err(e),
+    }
 }
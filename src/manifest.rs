@@ -0,0 +1,184 @@
+use crate::edits::CONFLICT_SUFFIX;
+use crate::schema::ThreadSource;
+use crate::utils::{parse_existing_frontmatter, write_atomic};
+use eyre::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+/// Per-directory export state, stored at `.zed-chat-export/state.json`.
+///
+/// Maps each thread id to the file it was exported to, the content hash of that
+/// export and the assets it references, so incremental runs can decide what to do
+/// without opening every markdown file. When the manifest is missing or unreadable it
+/// is rebuilt from the frontmatter of the markdown files on disk; when it merely lags
+/// behind them (a run was interrupted between checkpoints) only the unlisted files are
+/// read.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    version: u32,
+    pub threads: BTreeMap<String, ManifestEntry>,
+    /// Entries inserted since the last save.
+    #[serde(skip)]
+    unsaved: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Markdown file name, relative to the target directory.
+    pub path: String,
+    pub content_hash: String,
+    /// SHA-256 of the file bytes as last written, to detect local edits. Empty when
+    /// unknown (manifest rebuilt from disk); such a file counts as edited unless it
+    /// matches a fresh render.
+    #[serde(default)]
+    pub written_hash: String,
+    /// Asset file names under `assets/` referenced by the markdown file.
+    #[serde(default)]
+    pub assets: Vec<String>,
+    #[serde(default)]
+    pub source: ThreadSource,
+}
+
+impl Manifest {
+    const VERSION: u32 = 1;
+    pub const DIR: &'static str = ".zed-chat-export";
+    const FILE: &'static str = "state.json";
+    /// Save after this many inserts, so an interrupted run resumes close to where it
+    /// stopped.
+    const CHECKPOINT_EVERY: usize = 100;
+
+    fn path(target_dir: &Path) -> PathBuf {
+        target_dir.join(Self::DIR).join(Self::FILE)
+    }
+
+    /// Load the manifest for `target_dir`, reconciling it with the markdown files
+    /// present, or rebuilding it from frontmatter if it is missing.
+    pub fn load(target_dir: &Path) -> Self {
+        let loaded = fs::read(Self::path(target_dir))
+            .ok()
+            .and_then(|b| serde_json::from_slice::<Manifest>(&b).ok())
+            .filter(|m| m.version == Self::VERSION);
+
+        match loaded {
+            Some(mut m) => {
+                m.reconcile(target_dir);
+                m
+            }
+            None => Self::rebuild(target_dir),
+        }
+    }
+
+    /// Drop entries whose file is gone and add entries for markdown files the manifest
+    /// does not list. Only unlisted files are opened.
+    fn reconcile(&mut self, target_dir: &Path) {
+        let on_disk: BTreeSet<String> = markdown_files(target_dir).into_iter().collect();
+        self.threads.retain(|_, e| on_disk.contains(&e.path));
+        let listed: BTreeSet<String> = self.threads.values().map(|e| e.path.clone()).collect();
+        for name in on_disk.difference(&listed) {
+            if let Some((id, entry)) = entry_from_file(target_dir, name) {
+                self.threads.insert(id, entry);
+            }
+        }
+    }
+
+    /// Reconstruct the manifest by reading the frontmatter and asset links of every
+    /// markdown file in `target_dir`.
+    pub fn rebuild(target_dir: &Path) -> Self {
+        let threads = markdown_files(target_dir)
+            .iter()
+            .filter_map(|name| entry_from_file(target_dir, name))
+            .collect();
+        Self {
+            version: Self::VERSION,
+            threads,
+            unsaved: 0,
+        }
+    }
+
+    pub fn save(&self, target_dir: &Path) -> Result<()> {
+        let dir = target_dir.join(Self::DIR);
+        fs::create_dir_all(&dir).wrap_err("Failed to create state dir")?;
+        let json = serde_json::to_vec_pretty(self)?;
+        write_atomic(&Self::path(target_dir), &json).wrap_err("Failed to write export state")
+    }
+
+    /// Save if enough entries were inserted since the last save.
+    pub fn checkpoint(&mut self, target_dir: &Path) -> Result<()> {
+        if self.unsaved < Self::CHECKPOINT_EVERY {
+            return Ok(());
+        }
+        self.save(target_dir)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ManifestEntry> {
+        self.threads.get(id)
+    }
+
+    /// Absolute path of the file currently exported for `id`.
+    pub fn existing_path(&self, target_dir: &Path, id: &str) -> Option<PathBuf> {
+        self.threads.get(id).map(|e| target_dir.join(&e.path))
+    }
+
+    pub fn insert(&mut self, id: &str, entry: ManifestEntry) {
+        self.threads.insert(id.to_string(), entry);
+        self.unsaved += 1;
+    }
+}
+
+/// Manifest entry for an exported file, from its frontmatter and asset links. The
+/// written hash is unknown. Markdown files the exporter did not write (no
+/// `content_hash` and `exporter` block) are not adopted, so they are never updated or
+/// pruned.
+fn entry_from_file(target_dir: &Path, name: &str) -> Option<(String, ManifestEntry)> {
+    let path = target_dir.join(name);
+    let fm = parse_existing_frontmatter(&path).filter(|fm| fm.exporter)?;
+    let id = fm.id?;
+    let content_hash = fm.content_hash.filter(|h| !h.is_empty())?;
+    Some((
+        id,
+        ManifestEntry {
+            path: name.to_string(),
+            content_hash,
+            written_hash: String::new(),
+            assets: scan_asset_links(&path),
+            source: fm.source,
+        },
+    ))
+}
+
+fn markdown_files(target_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(target_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| n.ends_with(".md") && !n.ends_with(CONFLICT_SUFFIX))
+        .collect()
+}
+
+/// Collect the `./assets/<name>` link targets in a markdown file.
+pub fn scan_asset_links(path: &Path) -> Vec<String> {
+    const MARKER: &str = "](./assets/";
+    let Ok(file) = fs::File::open(path) else {
+        return Vec::new();
+    };
+    let mut assets = BTreeSet::new();
+    for line in BufReader::new(file).lines().map_while(|l| l.ok()) {
+        let mut rest = line.as_str();
+        while let Some(start) = rest.find(MARKER) {
+            rest = &rest[start + MARKER.len()..];
+            if let Some(end) = rest.find(')') {
+                assets.insert(rest[..end].to_string());
+                rest = &rest[end..];
+            }
+        }
+    }
+    assets.into_iter().collect()
+}
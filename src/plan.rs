@@ -0,0 +1,462 @@
+use crate::edits::{EditCheck, append_notes, check_user_edits, is_modified, written_hash};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::renderer::{self, Asset};
+use crate::schema::{SchemaError, ThreadDocument, ThreadSource};
+use crate::utils::{ExportConfig, content_hash, write_assets, write_atomic};
+use eyre::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// What an export run does (or, under `--dry-run`, would do) with one thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    /// Updated and moved to a new file name after a title change.
+    Rename,
+    Skip,
+    /// The exported file was edited locally and is left as-is.
+    Conflict,
+    /// The thread no longer exists in the database; its file is removed.
+    Prune,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Rename => "rename",
+            Action::Skip => "skip",
+            Action::Conflict => "conflict",
+            Action::Prune => "prune",
+        })
+    }
+}
+
+/// One entry of the plan. Paths are relative to the target directory.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub id: String,
+    pub action: Action,
+    pub path: String,
+    /// The previous file name of a renamed thread.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    /// Where the new export of a locally edited file goes, under `--on-edit conflict`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict_path: Option<String>,
+    /// Time spent deciding and writing, for the run report.
+    #[serde(skip)]
+    pub elapsed: Duration,
+}
+
+impl Change {
+    fn new(id: &str, action: Action, path: &Path) -> Self {
+        Self {
+            id: id.to_string(),
+            action,
+            path: file_name(path),
+            old_path: None,
+            conflict_path: None,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Warning to print for a locally edited file during a real run.
+    pub fn warning(&self) -> Option<String> {
+        if self.action != Action::Conflict {
+            return None;
+        }
+        Some(match &self.conflict_path {
+            Some(c) => format!("Warning: {} was edited locally; wrote {}", self.path, c),
+            None => format!(
+                "Warning: {} was edited locally; not overwriting (see --on-edit)",
+                self.path
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<9}", self.action)?;
+        if let Some(old) = &self.old_path {
+            write!(f, "{} -> ", old)?;
+        }
+        write!(f, "{}", self.path)?;
+        match (&self.conflict_path, self.action) {
+            (Some(c), _) => write!(f, " (edited locally; new export in {})", c),
+            (None, Action::Conflict) => write!(f, " (edited locally; left as-is)"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The file writes that carry out a decision.
+pub struct PendingWrite {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+    pub assets: Vec<Asset>,
+    /// Previous file of a renamed thread, removed once the new one is in place.
+    pub replaces: Option<PathBuf>,
+}
+
+impl PendingWrite {
+    pub fn apply(self, target_dir: &Path) -> Result<()> {
+        // Assets first, so a markdown file on disk never links to a missing asset.
+        write_assets(target_dir, &self.assets)?;
+        write_atomic(&self.path, &self.contents)?;
+        if let Some(old) = self.replaces {
+            fs::remove_file(&old)
+                .wrap_err_with(|| format!("Failed to remove renamed file: {}", old.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of deciding what to do with one thread, before anything is written.
+pub struct Decision {
+    pub change: Change,
+    pub write: Option<PendingWrite>,
+    /// Manifest entry to record once `write` is applied.
+    pub entry: Option<ManifestEntry>,
+}
+
+/// Decide what to do with thread `id`, rendering it if it needs writing. Reads the
+/// existing file to check for local edits but writes nothing.
+///
+/// `parse` reads the thread from its JSON (see [`crate::schema::Schemas::parse`]) and `allocate`
+/// picks the file stem; both are only called when the thread is not skipped.
+pub fn decide(
+    id: &str,
+    json_bytes: &[u8],
+    existing_path: Option<&Path>,
+    last: Option<&ManifestEntry>,
+    config: &ExportConfig,
+    parse: impl FnOnce(&[u8]) -> Result<ThreadDocument>,
+    allocate: impl FnOnce() -> String,
+) -> Result<Decision> {
+    let options = config.render_options();
+    let hash = content_hash(json_bytes, &options);
+    let unchanged = last.is_some_and(|e| e.content_hash == hash);
+    // Files adopted from disk have no written hash, so they are rendered again and
+    // compared before being skipped.
+    let unverified = last.is_some_and(|e| e.written_hash.is_empty());
+    if !config.force
+        && let Some(existing) = existing_path
+        && unchanged
+        && !unverified
+    {
+        return Ok(Decision {
+            change: Change::new(id, Action::Skip, existing),
+            write: None,
+            entry: None,
+        });
+    }
+
+    let thread = parse(json_bytes)?;
+
+    let stem = allocate();
+    let desired_path = config.target_dir.join(format!("{}.md", stem));
+
+    let mut buf: Vec<u8> = Vec::new();
+    let assets = match &thread {
+        ThreadDocument::Agent(thread) => renderer::render_thread(
+            &mut buf,
+            id,
+            &stem,
+            thread,
+            ThreadSource::Database,
+            &options,
+            &hash,
+        )
+        .wrap_err("Failed to render DbThread markdown")?,
+        ThreadDocument::Legacy(thread) => {
+            renderer::render_serialized_thread(&mut buf, id, thread, &options, &hash)
+                .wrap_err("Failed to render SerializedThread markdown")?
+        }
+        ThreadDocument::Text(thread) => {
+            renderer::render_text_thread(&mut buf, id, thread, &options, &hash)
+                .wrap_err("Failed to render text thread markdown")?;
+            None
+        }
+    };
+    let assets = assets.unwrap_or_default();
+
+    if !config.force
+        && unchanged
+        && let Some(last) = last.filter(|_| unverified)
+        && let Some(existing) = existing_path
+    {
+        // Nothing to write either way; record the written hash if the file is what
+        // this render produces, so later edits to it are detected.
+        let bytes = fs::read(existing).unwrap_or_default();
+        let entry = (written_hash(&bytes) == written_hash(&buf)).then(|| ManifestEntry {
+            written_hash: written_hash(&buf),
+            ..last.clone()
+        });
+        return Ok(Decision {
+            change: Change::new(id, Action::Skip, existing),
+            write: None,
+            entry,
+        });
+    }
+
+    let (write_path, notes) = match check_user_edits(
+        existing_path,
+        last.map(|e| e.written_hash.as_str()),
+        &buf,
+        &desired_path,
+        config.on_edit,
+    ) {
+        EditCheck::Skip => {
+            return Ok(Decision {
+                change: Change::new(id, Action::Conflict, existing_path.unwrap_or(&desired_path)),
+                write: None,
+                entry: None,
+            });
+        }
+        EditCheck::Write { path, notes } => (path, notes),
+    };
+    append_notes(&mut buf, notes.as_deref());
+
+    if write_path != desired_path {
+        let mut change = Change::new(id, Action::Conflict, existing_path.unwrap_or(&desired_path));
+        change.conflict_path = Some(file_name(&write_path));
+        return Ok(Decision {
+            change,
+            write: Some(PendingWrite {
+                path: write_path,
+                contents: buf,
+                assets,
+                replaces: None,
+            }),
+            entry: None,
+        });
+    }
+
+    let replaces = existing_path
+        .filter(|p| *p != desired_path)
+        .map(Path::to_path_buf);
+    let change = match (existing_path, &replaces) {
+        (None, _) => Change::new(id, Action::Create, &desired_path),
+        (Some(_), None) => Change::new(id, Action::Update, &desired_path),
+        (Some(old), Some(_)) => {
+            let mut c = Change::new(id, Action::Rename, &desired_path);
+            c.old_path = Some(file_name(old));
+            c
+        }
+    };
+
+    let entry = ManifestEntry {
+        path: change.path.clone(),
+        content_hash: hash,
+        written_hash: written_hash(&buf),
+        assets: assets.iter().map(|a| a.name.clone()).collect(),
+        source: thread.source(),
+    };
+    Ok(Decision {
+        change,
+        write: Some(PendingWrite {
+            path: desired_path,
+            contents: buf,
+            assets,
+            replaces,
+        }),
+        entry: Some(entry),
+    })
+}
+
+/// Plan the removal of exports whose thread is no longer in the database (`live`),
+/// and remove them unless this is a dry run. Files edited locally, or whose written
+/// hash is unknown, are kept and reported as conflicts.
+pub fn prune(
+    manifest: &mut Manifest,
+    live: &HashSet<&str>,
+    config: &ExportConfig,
+) -> Result<Vec<Change>> {
+    let gone: Vec<(String, ManifestEntry)> = manifest
+        .threads
+        .iter()
+        .filter(|(id, _)| !live.contains(id.as_str()))
+        .map(|(id, e)| (id.clone(), e.clone()))
+        .collect();
+
+    let mut changes = Vec::new();
+    for (id, entry) in gone {
+        let path = config.target_dir.join(&entry.path);
+        // Without a written hash nothing shows the file is unchanged since it was
+        // exported, so it is kept like an edited one.
+        let edited = entry.written_hash.is_empty()
+            || fs::read(&path).is_ok_and(|bytes| is_modified(&bytes, Some(&entry.written_hash)));
+        if edited {
+            changes.push(Change::new(&id, Action::Conflict, &path));
+            continue;
+        }
+        if !config.dry_run {
+            fs::remove_file(&path)
+                .wrap_err_with(|| format!("Failed to prune: {}", path.display()))?;
+            manifest.threads.remove(&id);
+        }
+        changes.push(Change::new(&id, Action::Prune, &path));
+    }
+    Ok(changes)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    /// The thread JSON did not deserialize ([`SchemaError`]).
+    Schema,
+    Other,
+}
+
+/// A thread that could not be exported, or the run itself failing (`id` is `None`).
+#[derive(Debug, Serialize)]
+pub struct ThreadError {
+    pub id: Option<String>,
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The error followed by its causes, outermost first.
+    pub chain: Vec<String>,
+}
+
+/// Every change decided during a run, and every error.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    pub errors: Vec<ThreadError>,
+    /// The run stopped early because `--max-errors` was reached.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub aborted: bool,
+}
+
+/// How a run went, as reported by the process exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    /// Some threads failed; the rest were exported.
+    Partial,
+    /// Every thread attempted failed.
+    Failed,
+    /// Threads failed to deserialize: the database likely comes from a newer Zed.
+    Schema,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> ExitCode {
+        match self {
+            Outcome::Success => ExitCode::SUCCESS,
+            Outcome::Partial => ExitCode::from(3),
+            Outcome::Failed => ExitCode::from(4),
+            Outcome::Schema => ExitCode::from(5),
+        }
+    }
+}
+
+impl Plan {
+    pub fn push(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    pub fn push_error(&mut self, id: Option<&str>, error: &eyre::Report) {
+        let kind = if error.chain().any(|e| e.is::<SchemaError>()) {
+            ErrorKind::Schema
+        } else {
+            ErrorKind::Other
+        };
+        self.errors.push(ThreadError {
+            id: id.map(str::to_string),
+            kind,
+            message: format!("{:#}", error),
+            chain: error.chain().map(|e| e.to_string()).collect(),
+        });
+    }
+
+    /// Whether `--max-errors` (or `--fail-fast`) says to stop.
+    pub fn error_limit_reached(&self, config: &ExportConfig) -> bool {
+        config
+            .max_errors
+            .is_some_and(|max| self.errors.len() >= max)
+    }
+
+    /// Schema errors win over the rest, since they point at a Zed update.
+    pub fn outcome(&self) -> Outcome {
+        if self.errors.is_empty() {
+            Outcome::Success
+        } else if self.errors.iter().any(|e| e.kind == ErrorKind::Schema) {
+            Outcome::Schema
+        } else if self.changes.is_empty() {
+            Outcome::Failed
+        } else {
+            Outcome::Partial
+        }
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.changes.iter().filter(|c| c.action == action).count()
+    }
+
+    /// "N created, N updated, N skipped", plus conflicts and prunes when present.
+    /// Renames count as updates.
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} created, {} updated, {} skipped",
+            self.count(Action::Create),
+            self.count(Action::Update) + self.count(Action::Rename),
+            self.count(Action::Skip),
+        );
+        let conflicts = self.count(Action::Conflict);
+        if conflicts > 0 {
+            s.push_str(&format!(", {} edited locally", conflicts));
+        }
+        let pruned = self.count(Action::Prune);
+        if pruned > 0 {
+            s.push_str(&format!(", {} pruned", pruned));
+        }
+        s
+    }
+
+    pub fn sort(&mut self) {
+        self.changes
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.id.cmp(&b.id)));
+    }
+
+    /// Print the plan of a dry run to stdout: one line per change (skips only when
+    /// `verbose`), or the whole plan as JSON. Stdout is left to the run report if it
+    /// goes there.
+    pub fn print(&mut self, config: &ExportConfig) -> Result<()> {
+        self.sort();
+        if config.report_to_stdout() {
+            // The report carries the plan.
+        } else if config.plan_json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        } else {
+            for change in &self.changes {
+                if change.action != Action::Skip || config.verbose {
+                    println!("{}", change);
+                }
+            }
+        }
+        if !config.quiet {
+            eprintln!("Dry run: {}. Nothing was written.", self.summary());
+        }
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
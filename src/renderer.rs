@@ -1,28 +1,315 @@
+use crate::assets::{self, ImageFormat};
 use crate::importer::{
-    AgentMessageContent, DbThread, MentionUri, Message, Role, SerializedMessageSegment,
-    SerializedThread, UserMessageContent,
+    AgentMessageContent, DbThread, LanguageModelImage, LanguageModelToolResultContent, MentionUri,
+    Message, Role, SerializedMessageSegment, SerializedThread, UserMessageContent, variant_name,
 };
+use crate::schema::ThreadSource;
+use crate::text_thread::TextThread;
 use base64::Engine as _;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
-use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
 use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Version of the markdown layout produced by this renderer. Bump it whenever a change
+/// alters the output for the same thread, so files written by older exporters are
+/// re-rendered on the next run.
+pub const RENDER_FORMAT: u32 = 6;
+
+/// Every user-selectable option that affects rendered output.
+///
+/// Recorded in frontmatter and folded into the content hash, so changing any of them
+/// re-renders existing files.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RenderOptions {
+    /// Sorted frontmatter tags. Already visible as top-level `tags`, so not repeated
+    /// in the `exporter` block.
+    #[serde(skip)]
+    pub tags: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub include_context: bool,
+    /// Downscale images larger than this many pixels on their longer side.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_max_dimension: Option<u32>,
+    #[serde(skip_serializing_if = "ImageFormat::is_original")]
+    pub image_format: ImageFormat,
+    /// Embed images up to this many KiB as data URIs instead of asset files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline_images: Option<u32>,
+    /// Write the content of file and fetch mentions to asset files and link them.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub mention_assets: bool,
+    /// Ids of the rules exported to `rules/`. Mentions of other rules are plain text.
+    /// Only whether there are any goes into the content hash, so saving a new rule
+    /// does not re-render every thread.
+    #[serde(skip)]
+    pub rules: Arc<BTreeSet<String>>,
+}
+
+impl RenderOptions {
+    pub fn new(
+        tags: Option<&[String]>,
+        include_context: bool,
+        image_max_dimension: Option<u32>,
+        image_format: ImageFormat,
+        inline_images: Option<u32>,
+        mention_assets: bool,
+        rules: Arc<BTreeSet<String>>,
+    ) -> Self {
+        Self {
+            tags: tags.map(|t| {
+                let mut v = t.to_vec();
+                v.sort();
+                v
+            }),
+            include_context,
+            image_max_dimension,
+            image_format,
+            inline_images,
+            mention_assets,
+            rules,
+        }
+    }
+
+    /// Stable byte encoding of the render format and all options, for hashing.
+    pub fn fingerprint(&self) -> Vec<u8> {
+        serde_json::to_vec(&(RENDER_FORMAT, &self.tags, self.rules.is_empty(), self))
+            .expect("options serialize")
+    }
+}
 
 pub struct Asset {
     pub name: String,
     pub data: Vec<u8>,
 }
 
-fn image_asset(stem: &str, b64: &str) -> Option<Asset> {
+fn image_asset(b64: &str, options: &RenderOptions) -> Option<Asset> {
     let bytes = base64::engine::general_purpose::STANDARD.decode(b64).ok()?;
+    let bytes = assets::shrink_image(bytes, options.image_max_dimension, options.image_format);
     let ext = infer::get(&bytes).map(|t| t.extension()).unwrap_or("bin");
-    let hash = format!("{:.6x}", Sha256::digest(&bytes));
-    let name = format!("{}.{}.{}", stem, hash, ext);
+    let name = assets::content_name(&bytes, ext);
     Some(Asset { name, data: bytes })
 }
 
+/// Render an @-mention and the content the model saw, in a form suited to its kind:
+/// code with its path and line range, diagnostics as a list, terminal output as a
+/// `console` block, rules as links into the exported rules library (when `rules`, the
+/// exported ids, has them) and URLs as links.
+fn write_mention<W: Write>(
+    writer: &mut W,
+    uri: &MentionUri,
+    content: &str,
+    rules: &BTreeSet<String>,
+) -> std::io::Result<()> {
+    let path_lang = |path: &Path| {
+        path.extension()
+            .map_or(String::new(), |e| e.to_string_lossy().to_string())
+    };
+    match uri {
+        MentionUri::File { abs_path } => {
+            let info = format!("{} {}", path_lang(abs_path), abs_path.display());
+            write_fenced(writer, info.trim(), content)
+        }
+        MentionUri::Symbol {
+            abs_path,
+            name,
+            line_range,
+        } => {
+            let info = format!(
+                "{} {}:{} {}",
+                path_lang(abs_path),
+                abs_path.display(),
+                lines(line_range),
+                name
+            );
+            write_fenced(writer, info.trim(), content)
+        }
+        MentionUri::Selection {
+            abs_path,
+            line_range,
+        } => {
+            let info = match abs_path {
+                Some(path) => format!(
+                    "{} {}:{}",
+                    path_lang(path),
+                    path.display(),
+                    lines(line_range)
+                ),
+                None => format!("untitled:{}", lines(line_range)),
+            };
+            write_fenced(writer, info.trim(), content)
+        }
+        MentionUri::Directory { abs_path } => {
+            writeln!(writer, "Directory `{}`:", abs_path.display())?;
+            write_fenced(writer, "", content)
+        }
+        MentionUri::Diagnostics {
+            include_errors,
+            include_warnings,
+        } => {
+            let kinds = match (include_errors, include_warnings) {
+                (true, true) => "errors and warnings",
+                (false, true) => "warnings",
+                _ => "errors",
+            };
+            writeln!(writer, "Diagnostics ({}):", kinds)?;
+            writeln!(writer)?;
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                writeln!(writer, "- {}", line)?;
+            }
+            writeln!(writer)
+        }
+        MentionUri::TerminalSelection { line_count } => {
+            let plural = if *line_count == 1 { "" } else { "s" };
+            writeln!(
+                writer,
+                "Terminal selection ({} line{}):",
+                line_count, plural
+            )?;
+            write_fenced(writer, "console", content)
+        }
+        MentionUri::Rule { id, name } => {
+            if rules.contains(id) {
+                writeln!(writer, "Rule [{}](./{}):", name, rule_path(id))?;
+            } else {
+                writeln!(writer, "Rule *{}*:", name)?;
+            }
+            write_quoted(writer, content)
+        }
+        MentionUri::Fetch { url } => {
+            writeln!(writer, "Fetched <{}>:", url)?;
+            write_fenced(writer, "md", content)
+        }
+        MentionUri::Thread { name, .. } => {
+            writeln!(writer, "Thread *{}*:", name)?;
+            write_quoted(writer, content)
+        }
+        MentionUri::TextThread { path, name } => {
+            writeln!(writer, "Text thread *{}* (`{}`):", name, path.display())?;
+            write_quoted(writer, content)
+        }
+        // The image itself follows as its own content item.
+        MentionUri::PastedImage => Ok(()),
+        MentionUri::Unknown(value) => {
+            write_unknown(writer, "mention", value)?;
+            write_fenced(writer, variant_name(value).unwrap_or_default(), content)
+        }
+    }
+}
+
+/// A 0-based inclusive line range as the 1-based `start-end` editors show.
+fn lines(range: &RangeInclusive<u32>) -> String {
+    format!("{}-{}", range.start() + 1, range.end() + 1)
+}
+
+/// Path of a rule in the exported rules library, relative to the target directory.
+/// Named by id alone, since a mention records the rule's name when it was made and
+/// rules can be renamed or share a name.
+pub fn rule_path(id: &str) -> String {
+    format!("rules/{}.md", id)
+}
+
+/// `content` in a fenced code block, with a fence longer than any backtick run inside
+/// it. Content that is already a fenced block is written as-is.
+fn write_fenced<W: Write>(writer: &mut W, info: &str, content: &str) -> std::io::Result<()> {
+    if content.trim_start().starts_with("```") {
+        return writeln!(writer, "{}", content);
+    }
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    writeln!(writer, "{}{}", fence, info)?;
+    writeln!(writer, "{}", content)?;
+    writeln!(writer, "{}", fence)
+}
+
+fn write_quoted<W: Write>(writer: &mut W, content: &str) -> std::io::Result<()> {
+    writeln!(writer)?;
+    for line in content.lines() {
+        if line.is_empty() {
+            writeln!(writer, ">")?;
+        } else {
+            writeln!(writer, "> {}", line)?;
+        }
+    }
+    writeln!(writer)
+}
+
+/// Link text and file name of a mention whose content is written as an asset with
+/// `mention_assets`: the path of a file, or the URL of a fetched page.
+fn mention_file(uri: &MentionUri) -> Option<(String, String)> {
+    let (label, basename) = match uri {
+        MentionUri::File { abs_path } => (
+            abs_path.to_string_lossy().to_string(),
+            abs_path.file_name()?.to_string_lossy().to_string(),
+        ),
+        // Zed stores fetched pages converted to markdown.
+        MentionUri::Fetch { url } => {
+            let page = url
+                .path_segments()
+                .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+                .or(url.host_str())
+                .unwrap_or("page");
+            let page = page.strip_suffix(".html").unwrap_or(page);
+            (url.to_string(), format!("{}.md", page))
+        }
+        _ => return None,
+    };
+    let basename = basename
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    Some((label, basename))
+}
+
+/// Link `image` from the markdown, with its size as the alt text when known, and
+/// add its asset to `assets`. With `inline_images`, images up to the cap are
+/// embedded as a data URI instead.
+fn write_image<W: Write>(
+    writer: &mut W,
+    image: &LanguageModelImage,
+    options: &RenderOptions,
+    assets: &mut Vec<Asset>,
+) -> std::io::Result<()> {
+    let Some(asset) = image_asset(&image.source, options) else {
+        return Ok(());
+    };
+    let alt = match image.size {
+        Some(size) if size.width > 0 && size.height > 0 => {
+            format!("image {}x{}", size.width, size.height)
+        }
+        _ => "image".to_string(),
+    };
+
+    let cap = options.inline_images.map(|kib| u64::from(kib) * 1024);
+    if cap.is_some_and(|cap| asset.data.len() as u64 <= cap) {
+        let mime = infer::get(&asset.data).map_or("application/octet-stream", |t| t.mime_type());
+        // The thread already holds the base64; only shrunk images need encoding.
+        let data = if options.image_max_dimension.is_none() && options.image_format.is_original() {
+            image.source.clone()
+        } else {
+            base64::engine::general_purpose::STANDARD.encode(&asset.data)
+        };
+        return writeln!(writer, "![{}](data:{};base64,{})", alt, mime, data);
+    }
+
+    writeln!(writer, "![{}](./assets/{})", alt, asset.name)?;
+    if !assets.iter().any(|a| a.name == asset.name) {
+        assets.push(asset);
+    }
+    Ok(())
+}
+
 #[derive(Serialize)]
-struct Frontmatter {
+struct Frontmatter<'a> {
     title: String,
     updated_at: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -32,8 +319,30 @@ struct Frontmatter {
     #[serde(skip_serializing_if = "Option::is_none")]
     git: Option<GitMetadata>,
     id: String,
-    #[serde(skip_serializing_if = "std::ops::Not::not")]
-    include_context: bool,
+    /// Only written for threads not stored in the database.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<ThreadSource>,
+    content_hash: String,
+    exporter: ExporterMetadata<'a>,
+}
+
+/// Which exporter build and options produced a file.
+#[derive(Serialize)]
+struct ExporterMetadata<'a> {
+    version: &'static str,
+    format: u32,
+    #[serde(flatten)]
+    options: &'a RenderOptions,
+}
+
+impl<'a> ExporterMetadata<'a> {
+    fn new(options: &'a RenderOptions) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            format: RENDER_FORMAT,
+            options,
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -52,8 +361,9 @@ pub fn render_thread<W: Write>(
     id: &str,
     stem: &str,
     thread: &DbThread,
-    tags: Option<&[String]>,
-    include_context: bool,
+    source: ThreadSource,
+    options: &RenderOptions,
+    content_hash: &str,
 ) -> std::io::Result<Option<Vec<Asset>>> {
     let model = thread
         .model
@@ -80,14 +390,12 @@ pub fn render_thread<W: Write>(
         title: thread.title.clone(),
         updated_at: thread.updated_at,
         model,
-        tags: tags.map(|t| {
-            let mut v = t.to_vec();
-            v.sort();
-            v
-        }),
+        tags: options.tags.clone(),
         git: git_info,
         id: id.to_string(),
-        include_context,
+        source: (source != ThreadSource::Database).then_some(source),
+        content_hash: content_hash.to_string(),
+        exporter: ExporterMetadata::new(options),
     };
 
     writeln!(writer, "---")?;
@@ -101,6 +409,7 @@ pub fn render_thread<W: Write>(
     writeln!(writer)?;
 
     let mut assets: Vec<Asset> = Vec::new();
+    let mut mentions = 0;
 
     for msg in &thread.messages {
         match msg {
@@ -113,64 +422,28 @@ pub fn render_thread<W: Write>(
                             writeln!(writer, "{}", text)?;
                         }
                         UserMessageContent::Mention { uri, content } => {
-                            if !include_context {
+                            if options.mention_assets
+                                && let Some((label, basename)) = mention_file(uri)
+                            {
+                                mentions += 1;
+                                let name = format!("{}/{}-{}", stem, mentions, basename);
+                                writeln!(writer, "[{}](./assets/{})", label, name)?;
+                                assets.push(Asset {
+                                    name,
+                                    data: content.clone().into_bytes(),
+                                });
                                 continue;
                             }
-                            let (path_str, lang_ext) = match uri {
-                                MentionUri::File { abs_path } => (
-                                    Some(abs_path.to_string_lossy().to_string()),
-                                    abs_path.extension(),
-                                ),
-                                MentionUri::Directory { abs_path } => {
-                                    (Some(abs_path.to_string_lossy().to_string()), None)
-                                }
-                                MentionUri::Symbol { abs_path, .. } => (
-                                    Some(abs_path.to_string_lossy().to_string()),
-                                    abs_path.extension(),
-                                ),
-                                MentionUri::Selection { abs_path, .. } => {
-                                    if let Some(p) = abs_path {
-                                        (Some(p.to_string_lossy().to_string()), p.extension())
-                                    } else {
-                                        (None, None)
-                                    }
-                                }
-                                MentionUri::TextThread { path, .. } => {
-                                    (Some(path.to_string_lossy().to_string()), path.extension())
-                                }
-                                MentionUri::Fetch { url } => (Some(url.to_string()), None),
-                                MentionUri::Thread { name, .. } => (Some(name.to_string()), None),
-                                MentionUri::Rule { name, .. } => (Some(name.to_string()), None),
-                                MentionUri::PastedImage => (Some("image".to_string()), None),
-                                MentionUri::Diagnostics { .. } => {
-                                    (Some("diagnostics".to_string()), None)
-                                }
-                                MentionUri::TerminalSelection { .. } => {
-                                    (Some("terminal".to_string()), None)
-                                }
-                            };
-
-                            let header = match (lang_ext.and_then(|s| s.to_str()), path_str) {
-                                (Some(l), Some(p)) => format!("{} {}", l, p),
-                                (Some(l), None) => l.to_string(),
-                                (None, Some(p)) => p,
-                                (None, None) => "".to_string(),
-                            };
-
-                            let trimmed = content.trim_start();
-                            if trimmed.starts_with("```") {
-                                writeln!(writer, "{}", content)?;
-                            } else {
-                                writeln!(writer, "```{}", header)?;
-                                writeln!(writer, "{}", content)?;
-                                writeln!(writer, "```")?;
+                            if !options.include_context {
+                                continue;
                             }
+                            write_mention(writer, uri, content, &options.rules)?;
                         }
                         UserMessageContent::Image(img) => {
-                            if let Some(asset) = image_asset(stem, &img.source) {
-                                writeln!(writer, "![image](./assets/{})", asset.name)?;
-                                assets.push(asset);
-                            }
+                            write_image(writer, img, options, &mut assets)?;
+                        }
+                        UserMessageContent::Unknown(value) => {
+                            write_unknown(writer, "content", value)?;
                         }
                     }
                 }
@@ -180,9 +453,30 @@ pub fn render_thread<W: Write>(
             Message::Agent(agent_msg) => {
                 writeln!(writer, "## Assistant")?;
                 writeln!(writer)?;
+                let mut shown = Vec::new();
                 for content in &agent_msg.content {
-                    if let AgentMessageContent::Text(text) = content {
-                        writeln!(writer, "{}", text)?;
+                    match content {
+                        AgentMessageContent::Text(text) => writeln!(writer, "{}", text)?,
+                        // Images returned by a tool (e.g. screenshots) go where it was called.
+                        AgentMessageContent::ToolUse(tool_use) => {
+                            if let Some(result) = agent_msg.tool_results.get(tool_use.id.as_str())
+                                && let LanguageModelToolResultContent::Image(img) = &result.content
+                            {
+                                write_image(writer, img, options, &mut assets)?;
+                                shown.push(tool_use.id.as_str());
+                            }
+                        }
+                        AgentMessageContent::Unknown(value) => {
+                            write_unknown(writer, "content", value)?
+                        }
+                        _ => {}
+                    }
+                }
+                for (id, result) in &agent_msg.tool_results {
+                    if let LanguageModelToolResultContent::Image(img) = &result.content
+                        && !shown.contains(&id.as_str())
+                    {
+                        write_image(writer, img, options, &mut assets)?;
                     }
                 }
                 writeln!(writer)?;
@@ -190,6 +484,10 @@ pub fn render_thread<W: Write>(
             Message::Resume => {
                 // Ignore resume messages
             }
+            Message::Unknown(value) => {
+                write_unknown(writer, "message", value)?;
+                writeln!(writer)?;
+            }
         }
     }
 
@@ -200,12 +498,33 @@ pub fn render_thread<W: Write>(
     })
 }
 
+/// Render a value the importer did not recognize (see `tolerant_enum!`) as a warning
+/// followed by its raw JSON, so nothing is silently dropped.
+fn write_unknown<W: Write>(
+    writer: &mut W,
+    kind: &str,
+    value: &serde_json::Value,
+) -> std::io::Result<()> {
+    let name = variant_name(value).unwrap_or("?");
+    writeln!(
+        writer,
+        "> **Warning:** unsupported {} `{}` (newer Zed version?), shown as raw JSON.",
+        kind, name
+    )?;
+    writeln!(writer)?;
+    writeln!(writer, "```json")?;
+    let json = serde_json::to_string_pretty(value).map_err(std::io::Error::other)?;
+    writeln!(writer, "{}", json)?;
+    writeln!(writer, "```")
+}
+
 pub fn render_serialized_thread<W: Write>(
     writer: &mut W,
     id: &str,
     thread: &SerializedThread,
-    tags: Option<&[String]>,
-) -> std::io::Result<()> {
+    options: &RenderOptions,
+    content_hash: &str,
+) -> std::io::Result<Option<Vec<Asset>>> {
     let model = thread
         .model
         .as_ref()
@@ -231,14 +550,12 @@ pub fn render_serialized_thread<W: Write>(
         title: thread.summary.clone(),
         updated_at: thread.updated_at,
         model,
-        tags: tags.map(|t| {
-            let mut v = t.to_vec();
-            v.sort();
-            v
-        }),
+        tags: options.tags.clone(),
         git: git_info,
         id: id.to_string(),
-        include_context: false,
+        source: None,
+        content_hash: content_hash.to_string(),
+        exporter: ExporterMetadata::new(options),
     };
 
     writeln!(writer, "---")?;
@@ -247,6 +564,8 @@ pub fn render_serialized_thread<W: Write>(
     writeln!(writer, "---")?;
     writeln!(writer)?;
 
+    let mut assets: Vec<Asset> = Vec::new();
+
     for msg in &thread.messages {
         let role_name = match msg.role {
             Role::User => "User",
@@ -262,8 +581,60 @@ pub fn render_serialized_thread<W: Write>(
                 writeln!(writer, "{}", text)?;
             }
         }
+        for result in &msg.tool_results {
+            if let LanguageModelToolResultContent::Image(img) = &result.content {
+                write_image(writer, img, options, &mut assets)?;
+            }
+        }
         writeln!(writer)?;
     }
 
+    Ok(if assets.is_empty() {
+        None
+    } else {
+        Some(assets)
+    })
+}
+
+pub fn render_text_thread<W: Write>(
+    writer: &mut W,
+    id: &str,
+    thread: &TextThread,
+    options: &RenderOptions,
+    content_hash: &str,
+) -> std::io::Result<()> {
+    let fm = Frontmatter {
+        title: thread.title.clone(),
+        updated_at: thread.updated_at,
+        model: None,
+        tags: options.tags.clone(),
+        git: None,
+        id: id.to_string(),
+        source: Some(ThreadSource::TextThread),
+        content_hash: content_hash.to_string(),
+        exporter: ExporterMetadata::new(options),
+    };
+
+    writeln!(writer, "---")?;
+    let yaml = serde_yaml::to_string(&fm).map_err(std::io::Error::other)?;
+    write!(writer, "{}", yaml)?;
+    writeln!(writer, "---")?;
+    writeln!(writer)?;
+
+    for (role, text) in thread.context.message_texts() {
+        // Zed keeps an empty user message at the end for the next prompt.
+        if text.trim().is_empty() {
+            continue;
+        }
+        let role_name = match role {
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+            Role::System => "System",
+        };
+        writeln!(writer, "## {}", role_name)?;
+        writeln!(writer)?;
+        writeln!(writer, "{}", text)?;
+        writeln!(writer)?;
+    }
     Ok(())
 }
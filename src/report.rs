@@ -0,0 +1,100 @@
+use crate::plan::{Action, Change, Outcome, Plan, ThreadError};
+use crate::utils::{ExportConfig, write_atomic};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use eyre::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+/// Format of the `--report` run report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+}
+
+/// Structured summary of an export run, for wrappers and indexers.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    version: u32,
+    exporter_version: &'static str,
+    dry_run: bool,
+    outcome: Outcome,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    aborted: bool,
+    target_dir: &'a Path,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    duration_ms: i64,
+    counts: Counts,
+    threads: Vec<ThreadReport<'a>>,
+    errors: &'a [ThreadError],
+}
+
+#[derive(Serialize)]
+struct Counts {
+    created: usize,
+    updated: usize,
+    renamed: usize,
+    skipped: usize,
+    conflicts: usize,
+    pruned: usize,
+    errors: usize,
+}
+
+#[derive(Serialize)]
+struct ThreadReport<'a> {
+    #[serde(flatten)]
+    change: &'a Change,
+    duration_ms: u128,
+}
+
+impl<'a> Report<'a> {
+    const VERSION: u32 = 1;
+
+    pub fn new(plan: &'a Plan, config: &'a ExportConfig, started_at: DateTime<Utc>) -> Self {
+        let finished_at = Utc::now();
+        Self {
+            version: Self::VERSION,
+            exporter_version: env!("CARGO_PKG_VERSION"),
+            dry_run: config.dry_run,
+            outcome: plan.outcome(),
+            aborted: plan.aborted,
+            target_dir: &config.target_dir,
+            started_at,
+            finished_at,
+            duration_ms: (finished_at - started_at).num_milliseconds(),
+            counts: Counts {
+                created: plan.count(Action::Create),
+                updated: plan.count(Action::Update),
+                renamed: plan.count(Action::Rename),
+                skipped: plan.count(Action::Skip),
+                conflicts: plan.count(Action::Conflict),
+                pruned: plan.count(Action::Prune),
+                errors: plan.errors.len(),
+            },
+            threads: plan
+                .changes
+                .iter()
+                .map(|change| ThreadReport {
+                    change,
+                    duration_ms: change.elapsed.as_millis(),
+                })
+                .collect(),
+            errors: &plan.errors,
+        }
+    }
+
+    /// Write the report to `config.report_path`, or stdout if unset or `-`.
+    pub fn write(&self, config: &ExportConfig) -> Result<()> {
+        let json = serde_json::to_vec_pretty(self)?;
+        match config.report_path.as_deref() {
+            Some(path) if path != Path::new("-") => {
+                write_atomic(path, &json).wrap_err("Failed to write run report")
+            }
+            _ => {
+                println!("{}", String::from_utf8_lossy(&json));
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use crate::archive::{RawArchive, read_archive};
+use crate::assets;
+use crate::edits::{CONFLICT_SUFFIX, strip_notes};
+use crate::importer::{
+    AgentMessage, AgentMessageContent, DbThread, GitState, ImageSize, LanguageModelImage, Message,
+    ProjectSnapshot, SerializedLanguageModel, SubagentContext, THREADS_TABLE_SQL,
+    TelemetryWorktreeSnapshot, TokenUsage, UserMessage, UserMessageContent, UserMessageId,
+};
+use crate::schema::ThreadSource;
+use crate::utils::NewDatabase;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use eyre::{Context, Result, eyre};
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub struct RestoreSummary {
+    pub restored: usize,
+    pub from_archive: usize,
+    pub skipped: usize,
+}
+
+/// A thread reconstructed from an export, ready to be written back as a DB row.
+struct RestoredRow {
+    id: String,
+    parent_id: Option<String>,
+    summary: String,
+    updated_at: DateTime<Utc>,
+    /// Uncompressed thread JSON for the `data` column.
+    json: Vec<u8>,
+}
+
+/// Rebuild a Zed `threads.db` at `output` from an export directory.
+///
+/// Threads found in the lossless raw archive (`source_dir/raw/`) are restored byte for
+/// byte, including ones Zed has since deleted. The rest are rebuilt from markdown, which
+/// is lossy: only text, images (from `./assets/`), title, model, timestamps and git
+/// metadata survive. Tool calls and thinking blocks are not recoverable. Text threads
+/// are left out, as Zed does not keep them in the database.
+pub fn restore(
+    source_dir: &Path,
+    output: &Path,
+    force: bool,
+    verbose: bool,
+) -> Result<RestoreSummary> {
+    let db = NewDatabase::create(output, force)?;
+
+    let mut paths: Vec<_> = fs::read_dir(source_dir)
+        .wrap_err_with(|| format!("Failed to read: {}", source_dir.display()))?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension().is_some_and(|e| e == "md")
+                && !p.to_string_lossy().ends_with(CONFLICT_SUFFIX)
+        })
+        .collect();
+    paths.sort();
+
+    let mut rows: BTreeMap<String, RestoredRow> = BTreeMap::new();
+    let mut skipped = 0usize;
+
+    let raw_dir = source_dir.join(RawArchive::DIR);
+    if raw_dir.is_dir() {
+        for (id, json) in read_archive(&raw_dir)? {
+            match json.and_then(|json| row_from_raw(id.clone(), json)) {
+                Ok(row) => {
+                    rows.insert(id, row);
+                }
+                Err(e) => {
+                    skipped += 1;
+                    eprintln!("Skipped raw/{}: {:#}", id, e);
+                }
+            }
+        }
+    }
+    let from_archive = rows.len();
+
+    for path in &paths {
+        match parse_markdown_export(path, source_dir) {
+            Ok(Some(row)) => {
+                rows.entry(row.id.clone()).or_insert(row);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                skipped += 1;
+                eprintln!("Skipped {}: {:#}", path.display(), e);
+            }
+        }
+    }
+
+    let mut conn = Connection::open(db.path())
+        .wrap_err_with(|| format!("Failed to create: {}", output.display()))?;
+    conn.execute_batch(THREADS_TABLE_SQL)
+        .wrap_err("Failed to create threads table")?;
+
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR REPLACE INTO threads (id, parent_id, summary, updated_at, data_type, data)
+             VALUES (?1, ?2, ?3, ?4, 'zstd', ?5)",
+        )?;
+        for row in rows.values() {
+            let data = zstd::encode_all(row.json.as_slice(), 3)
+                .wrap_err_with(|| format!("Failed to compress thread {}", row.id))?;
+            stmt.execute(params![
+                row.id,
+                row.parent_id,
+                row.summary,
+                row.updated_at.to_rfc3339(),
+                data
+            ])?;
+            if verbose {
+                eprintln!("Restored: {} ({})", row.summary, row.id);
+            }
+        }
+    }
+    tx.commit().wrap_err("Failed to commit restored threads")?;
+    drop(conn);
+    db.persist()?;
+
+    Ok(RestoreSummary {
+        restored: rows.len(),
+        from_archive,
+        skipped,
+    })
+}
+
+/// Build a row from archived JSON without interpreting the messages, so any
+/// schema version round-trips unchanged.
+fn row_from_raw(id: String, json: Vec<u8>) -> Result<RestoredRow> {
+    #[derive(Deserialize)]
+    struct Header {
+        #[serde(alias = "summary")]
+        title: String,
+        updated_at: DateTime<Utc>,
+        #[serde(default)]
+        subagent_context: Option<SubagentContext>,
+    }
+    let header: Header = serde_json::from_slice(&json).wrap_err("Invalid thread JSON")?;
+    Ok(RestoredRow {
+        id,
+        parent_id: header
+            .subagent_context
+            .map(|c| c.parent_thread_id.to_string()),
+        summary: header.title,
+        updated_at: header.updated_at,
+        json,
+    })
+}
+
+#[derive(Deserialize)]
+struct ExportedFrontmatter {
+    title: String,
+    updated_at: DateTime<Utc>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    git: Option<ExportedGit>,
+    id: String,
+    #[serde(default)]
+    source: ThreadSource,
+}
+
+#[derive(Deserialize)]
+struct ExportedGit {
+    path: String,
+    #[serde(default)]
+    remote: Option<String>,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    commit: Option<String>,
+}
+
+/// Rebuild a thread from its markdown export. `None` for a text thread, which Zed
+/// keeps in its own file rather than in `threads.db`.
+fn parse_markdown_export(path: &Path, source_dir: &Path) -> Result<Option<RestoredRow>> {
+    let text = fs::read_to_string(path).wrap_err("Failed to read file")?;
+    let rest = text
+        .strip_prefix("---\n")
+        .ok_or_else(|| eyre!("No frontmatter"))?;
+    let end = rest
+        .find("\n---\n")
+        .ok_or_else(|| eyre!("Unterminated frontmatter"))?;
+    let fm: ExportedFrontmatter =
+        serde_yaml::from_str(&rest[..end]).wrap_err("Invalid frontmatter")?;
+    if fm.source == ThreadSource::TextThread {
+        return Ok(None);
+    }
+    let body = strip_notes(&rest[end + "\n---\n".len()..]);
+
+    let model = fm.model.as_deref().and_then(|m| {
+        let (provider, model) = m.split_once('/')?;
+        Some(SerializedLanguageModel {
+            provider: provider.to_string(),
+            model: model.to_string(),
+        })
+    });
+
+    let initial_project_snapshot = fm.git.map(|g| ProjectSnapshot {
+        worktree_snapshots: vec![TelemetryWorktreeSnapshot {
+            worktree_path: g.path,
+            git_state: Some(GitState {
+                remote_url: g.remote,
+                head_sha: g.commit,
+                current_branch: g.branch,
+                diff: None,
+            }),
+        }],
+        timestamp: fm.updated_at,
+    });
+
+    let assets_dir = source_dir.join("assets");
+    let messages = split_sections(&body)
+        .into_iter()
+        .filter_map(|(role, content)| match role {
+            "User" => Some(Message::User(UserMessage {
+                id: UserMessageId::new(),
+                content: parse_user_content(&content, &assets_dir),
+            })),
+            "Assistant" => Some(Message::Agent(AgentMessage {
+                content: vec![AgentMessageContent::Text(content)],
+                ..Default::default()
+            })),
+            _ => None,
+        })
+        .collect();
+
+    let thread = DbThread {
+        title: fm.title,
+        messages,
+        updated_at: fm.updated_at,
+        detailed_summary: None,
+        initial_project_snapshot,
+        cumulative_token_usage: TokenUsage::default(),
+        request_token_usage: HashMap::new(),
+        model,
+        profile: None,
+        imported: false,
+        subagent_context: None,
+    };
+
+    Ok(Some(RestoredRow {
+        id: fm.id,
+        parent_id: None,
+        summary: thread.title.clone(),
+        updated_at: thread.updated_at,
+        json: thread.to_db_json()?,
+    }))
+}
+
+/// Split a rendered body into `(role, content)` pairs on `## User` / `## Assistant` /
+/// `## System` headings, ignoring headings inside fenced code blocks.
+fn split_sections(body: &str) -> Vec<(&'static str, String)> {
+    let mut sections: Vec<(&'static str, Vec<&str>)> = Vec::new();
+    // The opening fence of the block we are in: it only closes on a bare run of the
+    // same character at least as long, so fences nested in mention content are skipped.
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in body.lines() {
+        let trimmed = line.trim();
+        let run = |c: char| trimmed.chars().take_while(|&x| x == c).count();
+        match fence {
+            None => {
+                fence = ['`', '~']
+                    .into_iter()
+                    .map(|c| (c, run(c)))
+                    .find(|&(_, n)| n >= 3);
+            }
+            Some((c, n)) => {
+                if run(c) >= n && trimmed.chars().all(|x| x == c) {
+                    fence = None;
+                }
+            }
+        }
+        if fence.is_none() {
+            let role = match line.trim_end() {
+                "## User" => Some("User"),
+                "## Assistant" => Some("Assistant"),
+                "## System" => Some("System"),
+                _ => None,
+            };
+            if let Some(role) = role {
+                sections.push((role, Vec::new()));
+                continue;
+            }
+        }
+        if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line);
+        }
+    }
+
+    sections
+        .into_iter()
+        .map(|(role, lines)| (role, lines.join("\n").trim_matches('\n').to_string()))
+        .collect()
+}
+
+/// Bytes of an image link target: a file under `./assets/` that still exists, or an
+/// inlined base64 data URI.
+fn read_image(target: &str, assets_dir: &Path) -> Option<Vec<u8>> {
+    if let Some(name) = target.strip_prefix("./assets/") {
+        return fs::read(assets_dir.join(name)).ok();
+    }
+    let (_, data) = target.strip_prefix("data:")?.split_once(";base64,")?;
+    base64::engine::general_purpose::STANDARD.decode(data).ok()
+}
+
+/// Turn a user section back into content items, re-embedding inlined images and
+/// `./assets/` images that still exist on disk.
+fn parse_user_content(content: &str, assets_dir: &Path) -> Vec<UserMessageContent> {
+    let mut items = Vec::new();
+    let mut text: Vec<&str> = Vec::new();
+
+    for line in content.lines() {
+        let image = line
+            .strip_prefix("![image")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|rest| rest.split_once("]("))
+            .and_then(|(alt, target)| Some((alt, read_image(target, assets_dir)?)));
+        match image {
+            Some((alt, bytes)) => {
+                if !text.is_empty() {
+                    items.push(UserMessageContent::Text(text.join("\n")));
+                    text.clear();
+                }
+                let size = alt.trim().split_once('x').and_then(|(w, h)| {
+                    Some(ImageSize {
+                        width: w.parse().ok()?,
+                        height: h.parse().ok()?,
+                    })
+                });
+                items.push(UserMessageContent::Image(LanguageModelImage {
+                    source: base64::engine::general_purpose::STANDARD.encode(assets::to_png(bytes)),
+                    size,
+                }));
+            }
+            None => text.push(line),
+        }
+    }
+    if !text.is_empty() {
+        items.push(UserMessageContent::Text(text.join("\n")));
+    }
+    items
+}
@@ -0,0 +1,195 @@
+use crate::manifest::Manifest;
+use crate::renderer::rule_path;
+use crate::utils::write_atomic;
+use chrono::{DateTime, Utc};
+use eyre::{Context, Result, eyre};
+use heed::types::Bytes;
+use heed::{Database, EnvFlags, EnvOpenOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory under the target directory holding the exported rules library.
+pub const DIR: &str = "rules";
+
+/// Databases of Zed's prompt store. Both are keyed by the JSON of a `PromptId`.
+const METADATA_DB: &str = "metadata.v2";
+const BODIES_DB: &str = "bodies.v2";
+
+/// Key of an entry in the prompt store. Only user rules can be @-mentioned; the
+/// other kinds are overrides of Zed's built-in prompts.
+#[derive(Deserialize)]
+#[serde(tag = "kind")]
+enum PromptId {
+    User {
+        uuid: String,
+    },
+    #[serde(other)]
+    BuiltIn,
+}
+
+#[derive(Deserialize)]
+struct PromptMetadata {
+    id: PromptId,
+    title: Option<String>,
+    #[serde(default)]
+    default: bool,
+    saved_at: DateTime<Utc>,
+}
+
+/// A user rule from Zed's rules library.
+pub struct Rule {
+    pub id: String,
+    pub title: Option<String>,
+    /// Included in every new thread.
+    pub default: bool,
+    pub saved_at: DateTime<Utc>,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct Frontmatter<'a> {
+    title: &'a str,
+    id: &'a str,
+    default: bool,
+    saved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct RulesSummary {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+/// Read every user rule from the prompt store at `path` (the
+/// `prompts-library-db.0.mdb` directory), newest first. The store is opened
+/// read-only, so Zed can keep running.
+pub fn read_library(path: &Path) -> Result<Vec<Rule>> {
+    // SAFETY: the environment is opened read-only and only read within this
+    // function; LMDB allows other processes (Zed) to write it meanwhile.
+    let env = unsafe {
+        EnvOpenOptions::new()
+            .max_dbs(8)
+            .flags(EnvFlags::READ_ONLY)
+            .open(path)
+    }
+    .wrap_err_with(|| format!("Failed to open rules library: {}", path.display()))?;
+    let txn = env.read_txn()?;
+    let open = |name: &str| -> Result<Database<Bytes, Bytes>> {
+        env.open_database(&txn, Some(name))?
+            .ok_or_else(|| eyre!("Rules library has no {} database", name))
+    };
+    let metadata = open(METADATA_DB)?;
+    let bodies = open(BODIES_DB)?;
+
+    let mut rules = Vec::new();
+    for entry in metadata.iter(&txn)? {
+        let (key, value) = entry?;
+        let meta: PromptMetadata = serde_json::from_slice(value).wrap_err_with(|| {
+            format!(
+                "Failed to parse rule metadata: {}",
+                String::from_utf8_lossy(key)
+            )
+        })?;
+        let PromptId::User { uuid } = meta.id else {
+            continue;
+        };
+        let body = bodies
+            .get(&txn, key)?
+            .map(|body| String::from_utf8_lossy(body).into_owned())
+            .unwrap_or_default();
+        rules.push(Rule {
+            id: uuid,
+            title: meta.title.filter(|t| !t.is_empty()),
+            default: meta.default,
+            saved_at: meta.saved_at,
+            body,
+        });
+    }
+    rules.sort_by(|a, b| b.saved_at.cmp(&a.saved_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(rules)
+}
+
+fn render(rule: &Rule) -> Result<String> {
+    let fm = Frontmatter {
+        title: rule.title.as_deref().unwrap_or("Untitled"),
+        id: &rule.id,
+        default: rule.default,
+        saved_at: rule.saved_at,
+    };
+    let yaml = serde_yaml::to_string(&fm)?;
+    Ok(format!("---\n{}---\n\n{}\n", yaml, rule.body.trim_end()))
+}
+
+/// Ids of the rules whose files this exporter wrote, in `.zed-chat-export/rules.json`.
+/// Only these are ever pruned, so other files in `rules/` are left alone.
+fn index_path(target_dir: &Path) -> PathBuf {
+    target_dir.join(Manifest::DIR).join("rules.json")
+}
+
+/// Ids of the rule files the exporter wrote, from [`index_path`].
+fn tracked(target_dir: &Path) -> BTreeSet<String> {
+    fs::read(index_path(target_dir))
+        .ok()
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+/// Ids of the rules exported to `target_dir`: those the exporter wrote whose file is
+/// still there.
+pub fn exported(target_dir: &Path) -> BTreeSet<String> {
+    tracked(target_dir)
+        .into_iter()
+        .filter(|id| target_dir.join(rule_path(id)).is_file())
+        .collect()
+}
+
+/// Write each rule to `rules/<id>.md` under `target_dir`, where thread mentions of
+/// it link ([`rule_path`]), leaving files whose content is unchanged alone. With
+/// `prune`, the files of rules no longer in the library are removed.
+pub fn export(target_dir: &Path, rules: &[Rule], prune: bool) -> Result<RulesSummary> {
+    let dir = target_dir.join(DIR);
+    fs::create_dir_all(&dir).wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    let mut tracked = tracked(target_dir);
+
+    let mut summary = RulesSummary::default();
+    for rule in rules {
+        let path = target_dir.join(rule_path(&rule.id));
+        let text = render(rule)?;
+        tracked.insert(rule.id.clone());
+        if fs::read(&path).is_ok_and(|old| old == text.as_bytes()) {
+            summary.unchanged += 1;
+            continue;
+        }
+        write_atomic(&path, text.as_bytes())?;
+        summary.written += 1;
+    }
+
+    if prune {
+        let live: BTreeSet<&str> = rules.iter().map(|rule| rule.id.as_str()).collect();
+        let gone: Vec<String> = tracked
+            .iter()
+            .filter(|id| !live.contains(id.as_str()))
+            .cloned()
+            .collect();
+        for id in gone {
+            let path = target_dir.join(rule_path(&id));
+            if path.exists() {
+                fs::remove_file(&path)
+                    .wrap_err_with(|| format!("Failed to remove rule: {}", path.display()))?;
+                summary.removed += 1;
+            }
+            tracked.remove(&id);
+        }
+    }
+
+    fs::create_dir_all(target_dir.join(Manifest::DIR)).wrap_err("Failed to create state dir")?;
+    write_atomic(
+        &index_path(target_dir),
+        &serde_json::to_vec_pretty(&tracked)?,
+    )
+    .wrap_err("Failed to write rules index")?;
+    Ok(summary)
+}
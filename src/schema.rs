@@ -0,0 +1,261 @@
+//! Registry of the thread storage formats Zed has used, and the per-row dispatch
+//! between them.
+//!
+//! Zed keeps every thread in one `threads` table, but the JSON in each row is in
+//! whichever format the Zed build that last saved it wrote. A database from a
+//! current Zed therefore mixes generations. Each [`Schema`] names one generation,
+//! the `version` values it covers, and how to deserialize it with the types in
+//! [`crate::importer`]. Every generation is stored in the same columns
+//! ([`COLUMNS`]): [`Schemas::detect`] checks the table has them once, then the
+//! dispatch is per row, on the JSON. Export and `doctor` share it, so they agree
+//! on every row.
+//!
+//! Supporting a new generation means adding its types to the importer and an entry
+//! to [`REGISTRY`]; newest first, since unversioned rows try each in order.
+
+use crate::importer::{DbThread, SerializedThread};
+use crate::text_thread::TextThread;
+use clap::ValueEnum;
+use eyre::{Context, Result};
+use rusqlite::Connection;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Name of a registered schema, as given to `--zed-schema`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaName {
+    /// `DbThread` JSON 0.3.0, written by the current agent panel.
+    Agent,
+    /// `SerializedThread` JSON 0.1.0 / 0.2.0, from before the agent panel rewrite.
+    Legacy,
+}
+
+impl fmt::Display for SchemaName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            SchemaName::Agent => "agent",
+            SchemaName::Legacy => "legacy",
+        })
+    }
+}
+
+/// A thread deserialized by one of the registered schemas, or a text thread read
+/// from its file.
+pub enum ThreadDocument {
+    Agent(DbThread),
+    Legacy(SerializedThread),
+    Text(TextThread),
+}
+
+impl ThreadDocument {
+    pub fn source(&self) -> ThreadSource {
+        match self {
+            ThreadDocument::Text(_) => ThreadSource::TextThread,
+            _ => ThreadSource::Database,
+        }
+    }
+}
+
+/// Where Zed stores a thread. Recorded in the manifest and, for text threads, in
+/// the frontmatter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadSource {
+    /// A row of the `threads` table.
+    #[default]
+    Database,
+    /// A `conversations/*.zed.json` file.
+    TextThread,
+    /// A `SharedThread` file rendered with `render-shared`. Never pruned.
+    Shared,
+}
+
+/// One generation of Zed's thread format.
+pub struct Schema {
+    pub name: SchemaName,
+    /// Values of the JSON `version` field written in this format.
+    pub json_versions: &'static [&'static str],
+    /// Zed releases known to write it.
+    pub zed_versions: &'static str,
+    parse: fn(&[u8]) -> serde_json::Result<ThreadDocument>,
+    check: fn(&[u8]) -> Result<ThreadDocument, PathError>,
+}
+
+/// A deserialization error with the path of the field that failed.
+pub type PathError = serde_path_to_error::Error<serde_json::Error>;
+
+impl Schema {
+    pub fn parse(&self, json_bytes: &[u8]) -> serde_json::Result<ThreadDocument> {
+        (self.parse)(json_bytes)
+    }
+
+    /// Like [`Schema::parse`], but slower and locating the failure.
+    pub fn check(&self, json_bytes: &[u8]) -> Result<ThreadDocument, PathError> {
+        (self.check)(json_bytes)
+    }
+}
+
+fn check<T: DeserializeOwned>(json_bytes: &[u8]) -> Result<T, PathError> {
+    serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_slice(json_bytes))
+}
+
+/// Every schema the importer can read, newest first.
+pub static REGISTRY: [Schema; 2] = [
+    Schema {
+        name: SchemaName::Agent,
+        json_versions: &[DbThread::VERSION],
+        zed_versions: "current, verified against 0.225.9",
+        parse: |json| serde_json::from_slice(json).map(ThreadDocument::Agent),
+        check: |json| check(json).map(ThreadDocument::Agent),
+    },
+    Schema {
+        name: SchemaName::Legacy,
+        json_versions: &["0.1.0", "0.2.0"],
+        zed_versions: "original agent panel; still read by current Zed",
+        parse: |json| serde_json::from_slice(json).map(ThreadDocument::Legacy),
+        check: |json| check(json).map(ThreadDocument::Legacy),
+    },
+];
+
+pub fn get(name: SchemaName) -> &'static Schema {
+    REGISTRY.iter().find(|s| s.name == name).unwrap()
+}
+
+/// The schema that declares `version`, if any.
+pub fn for_version(version: &str) -> Option<&'static Schema> {
+    REGISTRY.iter().find(|s| s.json_versions.contains(&version))
+}
+
+/// Columns of the `threads` table the export reads, whatever the format of a row.
+pub const COLUMNS: &[&str] = &["id", "summary", "updated_at", "data_type", "data"];
+
+/// Columns in [`COLUMNS`] that `columns` (from [`table_columns`]) lacks.
+pub fn missing_columns(columns: &[(String, String)]) -> Vec<&'static str> {
+    COLUMNS
+        .iter()
+        .copied()
+        .filter(|c| !columns.iter().any(|(name, _)| name == c))
+        .collect()
+}
+
+/// `(name, declared type)` of each column of the `threads` table; empty if there is
+/// no such table.
+pub fn table_columns(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("PRAGMA table_info(threads)")?;
+    stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+    })?
+    .collect::<Result<_, _>>()
+    .wrap_err("Failed to read table schema")
+}
+
+/// Read the JSON `version` field without deserializing the rest of the thread.
+pub fn json_version(json_bytes: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Probe {
+        version: Option<String>,
+    }
+    serde_json::from_slice::<Probe>(json_bytes).ok()?.version
+}
+
+/// The `threads` table lacks columns the export reads.
+#[derive(Debug)]
+pub struct IncompatibleTable {
+    pub missing: Vec<&'static str>,
+}
+
+impl fmt::Display for IncompatibleTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The threads table has no {} column(s); run `zed-chat-export doctor` for details",
+            self.missing.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for IncompatibleTable {}
+
+/// A thread whose JSON does not deserialize with the schema chosen for it, usually
+/// because Zed changed its format.
+#[derive(Debug)]
+pub struct SchemaError {
+    pub schema: SchemaName,
+    pub version: Option<String>,
+    pub source: serde_json::Error,
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not deserialize as the {} schema", self.schema)?;
+        match &self.version {
+            Some(v) if for_version(v).is_none() => write!(
+                f,
+                " (unknown JSON version {}; try --zed-schema if it is compatible)",
+                v
+            ),
+            Some(v) => write!(f, " (JSON version {})", v),
+            None => write!(f, " (no JSON version)"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// How to pick the schema of each row of one database: by its `version`, falling
+/// back to `--zed-schema` if given.
+pub struct Schemas {
+    forced: Option<&'static Schema>,
+}
+
+impl Schemas {
+    /// Check the `threads` table of `conn` has every column in [`COLUMNS`]. Fails
+    /// with [`IncompatibleTable`] if not.
+    pub fn detect(conn: &Connection, forced: Option<SchemaName>) -> Result<Self> {
+        let columns = table_columns(conn)?;
+        let missing = missing_columns(&columns);
+        if !missing.is_empty() {
+            return Err(IncompatibleTable { missing }.into());
+        }
+        Ok(Self {
+            forced: forced.map(get),
+        })
+    }
+
+    /// The schemas to try for a row with `version`, in order: the one it declares,
+    /// else the forced one, else every registered one.
+    pub fn candidates(&self, version: Option<&str>) -> Vec<&'static Schema> {
+        match version.and_then(for_version).or(self.forced) {
+            Some(schema) => vec![schema],
+            None => REGISTRY.iter().collect(),
+        }
+    }
+
+    /// Deserialize a thread with the first of its [candidates](Schemas::candidates)
+    /// that fits; if none does, the first one's error is reported.
+    pub fn parse(&self, json_bytes: &[u8]) -> Result<ThreadDocument> {
+        let version = json_version(json_bytes);
+        let mut first_error = None;
+        for schema in self.candidates(version.as_deref()) {
+            match schema.parse(json_bytes) {
+                Ok(thread) => return Ok(thread),
+                Err(e) => {
+                    first_error.get_or_insert((schema.name, e));
+                }
+            }
+        }
+        let (schema, source) = first_error.expect("at least one candidate schema");
+        Err(SchemaError {
+            schema,
+            version,
+            source,
+        }
+        .into())
+    }
+}
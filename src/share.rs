@@ -0,0 +1,138 @@
+use crate::edits::written_hash;
+use crate::engine::FileNames;
+use crate::importer::{DbThread, SharedThread};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::renderer::{self, RenderOptions};
+use crate::schema::ThreadSource;
+use crate::utils::{content_hash, decompress, open_db, write_assets, write_atomic};
+use eyre::{Context, Result, eyre};
+use rusqlite::{Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Write the thread `id` (full id or unique prefix) as a Zed `SharedThread` payload.
+/// Returns the path written.
+pub fn export_shared(db_path: &Path, id: &str, output: Option<PathBuf>) -> Result<PathBuf> {
+    let conn = open_db(db_path)?;
+    let id = resolve_thread_id(&conn, id)?;
+
+    let (data_type, data): (String, Vec<u8>) = conn
+        .query_row(
+            "SELECT data_type, data FROM threads WHERE id = ?",
+            [&id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .wrap_err_with(|| format!("Failed to fetch thread {}", id))?;
+
+    let json_bytes = decompress(&data_type, &data)?;
+    let thread: DbThread = serde_json::from_slice(&json_bytes).wrap_err_with(|| {
+        format!(
+            "Thread {} is not in the current DbThread format (legacy threads cannot be shared)",
+            id
+        )
+    })?;
+
+    let shared = SharedThread::from_db_thread(&thread);
+    let bytes = shared
+        .to_bytes()
+        .wrap_err("Failed to encode shared thread")?;
+
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("{}.zed-thread", id)));
+    write_atomic(&output, &bytes)?;
+    Ok(output)
+}
+
+/// Render a `SharedThread` payload into `target_dir` as markdown, with its assets,
+/// and record it in the manifest as [`ThreadSource::Shared`] so `--prune` leaves it
+/// alone. Returns the path of the markdown file.
+pub fn render_shared(input: &Path, target_dir: &Path, options: &RenderOptions) -> Result<PathBuf> {
+    let bytes = fs::read(input).wrap_err_with(|| format!("Failed to read: {}", input.display()))?;
+    let shared = SharedThread::from_bytes(&bytes)
+        .wrap_err_with(|| format!("Not a shared thread: {}", input.display()))?;
+    if shared.version != SharedThread::VERSION {
+        eprintln!(
+            "Warning: shared thread version {} (expected {})",
+            shared.version,
+            SharedThread::VERSION
+        );
+    }
+
+    // Shared payloads carry no id; derive a stable one from the content so
+    // rendering the same file twice targets the same markdown file.
+    let id = Uuid::from_slice(&Sha256::digest(&bytes)[..16])
+        .expect("16 bytes")
+        .to_string();
+    let hash = content_hash(&bytes, options);
+    let thread = shared.into_db_thread();
+
+    fs::create_dir_all(target_dir).wrap_err("Failed to create target dir")?;
+    let mut manifest = Manifest::load(target_dir);
+    let stem = FileNames::new(&manifest).allocate(&id, &thread.title);
+    let name = format!("{}.md", stem);
+    let path = target_dir.join(&name);
+
+    let mut buf: Vec<u8> = Vec::new();
+    let assets = renderer::render_thread(
+        &mut buf,
+        &id,
+        &stem,
+        &thread,
+        ThreadSource::Shared,
+        options,
+        &hash,
+    )?
+    .unwrap_or_default();
+
+    if !assets.is_empty() {
+        fs::create_dir_all(target_dir.join("assets")).wrap_err("Failed to create assets dir")?;
+        write_assets(target_dir, &assets)?;
+    }
+    write_atomic(&path, &buf)?;
+
+    manifest.insert(
+        &id,
+        ManifestEntry {
+            path: name,
+            content_hash: hash,
+            written_hash: written_hash(&buf),
+            assets: assets.into_iter().map(|a| a.name).collect(),
+            source: ThreadSource::Shared,
+        },
+    );
+    manifest.save(target_dir)?;
+
+    Ok(path)
+}
+
+/// Resolve a full thread id or a unique id prefix (as used in exported filenames).
+pub fn resolve_thread_id(conn: &Connection, id_or_prefix: &str) -> Result<String> {
+    let exact: Option<String> = conn
+        .query_row(
+            "SELECT id FROM threads WHERE id = ?",
+            [id_or_prefix],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(id) = exact {
+        return Ok(id);
+    }
+
+    let mut stmt = conn.prepare("SELECT id FROM threads WHERE substr(id, 1, ?1) = ?2 LIMIT 2")?;
+    let matches: Vec<String> = stmt
+        .query_map(
+            rusqlite::params![id_or_prefix.len() as i64, id_or_prefix],
+            |row| row.get(0),
+        )?
+        .collect::<Result<_, _>>()?;
+
+    match matches.as_slice() {
+        [id] => Ok(id.clone()),
+        [] => Err(eyre!("No thread found with id {:?}", id_or_prefix)),
+        _ => Err(eyre!(
+            "Thread id prefix {:?} is ambiguous; use a longer prefix",
+            id_or_prefix
+        )),
+    }
+}
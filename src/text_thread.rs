@@ -0,0 +1,116 @@
+//! Text threads: conversations of Zed's editor-based assistant panel, which predate
+//! the agent panel and are stored as `conversations/*.zed.json` files rather than
+//! in `threads.db`. They are exported to the same directory, in the same format.
+
+use crate::importer::SavedContext;
+use crate::schema::ThreadDocument;
+use chrono::{DateTime, Utc};
+use eyre::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// Extension of a text thread file.
+const EXTENSION: &str = ".zed.json";
+
+/// A text thread as parsed for rendering, with the title and time of its file.
+pub struct TextThread {
+    pub context: SavedContext,
+    pub title: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A text thread file found on disk, not yet parsed.
+pub struct TextThreadFile {
+    /// The thread's own id, or one derived from the file name for files written
+    /// before text threads had ids.
+    pub id: String,
+    /// The thread's summary, or the file name for untitled threads.
+    pub title: String,
+    /// The file's modification time; text threads record no timestamp of their own.
+    pub updated_at: DateTime<Utc>,
+    pub json: Vec<u8>,
+}
+
+/// Every text thread file in `dir`, newest first.
+pub fn discover(dir: &Path) -> Result<Vec<TextThreadFile>> {
+    let entries =
+        fs::read_dir(dir).wrap_err_with(|| format!("Failed to read: {}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some(stem) = name.strip_suffix(EXTENSION) else {
+            continue;
+        };
+        let json =
+            fs::read(&path).wrap_err_with(|| format!("Failed to read: {}", path.display()))?;
+        let updated_at = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .map_or_else(|_| Utc::now(), DateTime::<Utc>::from);
+
+        // Read just the id and title here; a file that does not parse fails later,
+        // as an error of that thread.
+        #[derive(Deserialize)]
+        struct Probe {
+            id: Option<String>,
+            summary: Option<String>,
+        }
+        let probe = serde_json::from_slice::<Probe>(&json).ok();
+        let (id, summary) = probe.map_or((None, None), |p| (p.id, p.summary));
+        files.push(TextThreadFile {
+            id: id
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(|| id_from_name(name)),
+            title: summary
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| title_from_stem(stem)),
+            updated_at,
+            json,
+        });
+    }
+    files.sort_by(|a, b| {
+        b.updated_at
+            .cmp(&a.updated_at)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    Ok(files)
+}
+
+/// Parse the JSON of text thread `file`.
+pub fn parse(json_bytes: &[u8], file: &TextThreadFile) -> Result<ThreadDocument> {
+    let context: SavedContext =
+        serde_json::from_slice(json_bytes).wrap_err("Could not deserialize as a text thread")?;
+    Ok(ThreadDocument::Text(TextThread {
+        context,
+        title: file.title.clone(),
+        updated_at: file.updated_at,
+    }))
+}
+
+/// A UUID-shaped id derived from a file name, so it is stable across runs and
+/// restores into a valid Zed thread id.
+fn id_from_name(name: &str) -> String {
+    let hex = format!("{:x}", Sha256::digest(name.as_bytes()));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Zed names text thread files `<summary> - <n>`; the title is the part before the
+/// counter.
+fn title_from_stem(stem: &str) -> String {
+    match stem.rsplit_once(" - ") {
+        Some((title, n)) if n.bytes().all(|b| b.is_ascii_digit()) => title.to_string(),
+        _ => stem.to_string(),
+    }
+}
@@ -1,9 +1,21 @@
-use chrono::{DateTime, Utc};
+use crate::archive::RawFormat;
+use crate::assets::{ImageFormat, is_content_name};
+use crate::edits::EditPolicy;
+use crate::renderer::{Asset, RenderOptions};
+use crate::report::ReportFormat;
+use crate::schema::{SchemaName, ThreadSource};
 use eyre::{Context, Result, eyre};
-use serde::Deserialize;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use rusqlite::{Connection, OpenFlags};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name prefix of in-progress writes. Leftovers from an interrupted run are removed
+/// by [`remove_stale_temp_files`].
+pub const TEMP_PREFIX: &str = ".zed-chat-export-tmp";
 
 /// Configuration required to run the export process.
 /// This decouples the logic from how the arguments were parsed (CLI/Config file).
@@ -16,41 +28,85 @@ pub struct ExportConfig {
     pub verbose: bool,
     pub quiet: bool,
     pub include_context: bool,
+    pub archive_raw: Option<RawFormat>,
+    pub on_edit: EditPolicy,
+    /// Decide everything but write nothing; print the plan instead.
+    pub dry_run: bool,
+    /// Print the dry-run plan as JSON.
+    pub plan_json: bool,
+    /// Remove exports of threads that are no longer in the database.
+    pub prune: bool,
+    /// Write a structured run report in this format.
+    pub report: Option<ReportFormat>,
+    /// Where to write the report; stdout if unset or `-`.
+    pub report_path: Option<PathBuf>,
+    /// Stop the run once this many threads have failed.
+    pub max_errors: Option<usize>,
+    /// Schema for threads whose JSON version is not in the registry.
+    pub zed_schema: Option<SchemaName>,
+    /// Downscale images larger than this many pixels on their longer side.
+    pub image_max_dimension: Option<u32>,
+    /// Format PNG images are converted to.
+    pub image_format: ImageFormat,
+    /// Embed images up to this many KiB in the markdown.
+    pub inline_images: Option<u32>,
+    /// Write file and fetch mention content to asset files.
+    pub mention_assets: bool,
+    /// Directory of text thread files to export along with the database.
+    pub text_threads_dir: Option<PathBuf>,
+    /// Ids of the rules in `target_dir/rules/`, which rule mentions link to.
+    pub rules: Arc<BTreeSet<String>>,
 }
 
-#[derive(Clone, Copy)]
-pub enum ProcessResult {
-    Created,
-    Updated,
-    Skipped,
+impl ExportConfig {
+    pub fn report_to_stdout(&self) -> bool {
+        self.report.is_some()
+            && self
+                .report_path
+                .as_deref()
+                .is_none_or(|p| p == Path::new("-"))
+    }
+
+    pub fn render_options(&self) -> RenderOptions {
+        RenderOptions::new(
+            self.tags.as_deref(),
+            self.include_context,
+            self.image_max_dimension,
+            self.image_format,
+            self.inline_images,
+            self.mention_assets,
+            self.rules.clone(),
+        )
+    }
 }
 
 #[derive(Clone)]
 pub struct FileFrontmatter {
     pub id: Option<String>,
-    pub updated_at: DateTime<Utc>,
-    pub include_context: bool,
+    pub content_hash: Option<String>,
+    /// Whether the frontmatter has the `exporter` block this tool writes.
+    pub exporter: bool,
+    pub source: ThreadSource,
+}
+
+/// Open the thread database read-only, tuned for bulk reads.
+pub fn open_db(path: &Path) -> Result<Connection> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .wrap_err("Failed to open database")?;
+    conn.execute_batch("PRAGMA cache_size = -16384;")
+        .wrap_err("Failed to set cache_size")?;
+    Ok(conn)
 }
 
-/// Create a read-only backup of the database to a temporary file.
-#[cfg(feature = "sequential")]
-pub fn backup_database(db_path: &Path, quiet: bool) -> Result<NamedTempFile> {
-    use rusqlite::{Connection, OpenFlags, backup::Backup};
+/// Copy the database to a temporary file, so the export reads one consistent
+/// snapshot while Zed keeps writing.
+pub fn backup_database(db_path: &Path) -> Result<tempfile::NamedTempFile> {
+    use rusqlite::backup::Backup;
     use std::time::Duration;
     use tempfile::NamedTempFile;
-    let spinner = if quiet {
-        indicatif::ProgressBar::hidden()
-    } else {
-        let s = indicatif::ProgressBar::new_spinner();
-        s.set_style(
-            indicatif::ProgressStyle::with_template("{spinner:.green} {msg}")
-                .unwrap()
-                .tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
-        );
-        s.set_message("Snapshotting database...");
-        s.enable_steady_tick(Duration::from_millis(80));
-        s
-    };
 
     let src = Connection::open_with_flags(
         db_path,
@@ -70,19 +126,129 @@ pub fn backup_database(db_path: &Path, quiet: bool) -> Result<NamedTempFile> {
     }
 
     drop(src);
-    spinner.finish_and_clear();
     Ok(tmp)
 }
 
-/// Cheaply extract `updated_at` from JSON without full deserialization.
-pub fn extract_json_timestamp(json_bytes: &[u8]) -> Option<DateTime<Utc>> {
-    #[derive(Deserialize)]
-    struct Minimal {
-        updated_at: DateTime<Utc>,
+/// SHA-256 over the decompressed thread JSON, the render format version and every
+/// option that affects the rendered output. Stored in frontmatter; a thread is
+/// re-rendered whenever it changes.
+pub fn content_hash(json_bytes: &[u8], options: &RenderOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(json_bytes);
+    hasher.update(options.fingerprint());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Write `data` to `path` via a temp file in the same directory and a rename, so an
+/// interrupted run never leaves a truncated file behind.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = temp_file_for(path)?;
+    tmp.write_all(data)
+        .and_then(|()| tmp.as_file().sync_all())
+        .wrap_err_with(|| format!("Failed to write: {}", path.display()))?;
+    tmp.persist(path)
+        .wrap_err_with(|| format!("Failed to move into place: {}", path.display()))?;
+    Ok(())
+}
+
+/// A temp file next to `path` that will be renamed over it. It gets the mode of the
+/// file it replaces, or that of a new file (`0666` less the umask) rather than the
+/// owner-only mode temp files are created with.
+fn temp_file_for(path: &Path) -> Result<tempfile::NamedTempFile> {
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(TEMP_PREFIX);
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    let tmp = builder
+        .tempfile_in(dir)
+        .wrap_err_with(|| format!("Failed to create temp file in {}", dir.display()))?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(tmp.path(), metadata.permissions())
+            .wrap_err_with(|| format!("Failed to set permissions like {}", path.display()))?;
+    }
+    Ok(tmp)
+}
+
+/// A database built in a temp file next to its destination and moved over it by
+/// [`NewDatabase::persist`] once complete, so a failed run leaves the file it would
+/// have replaced untouched.
+pub struct NewDatabase {
+    file: tempfile::NamedTempFile,
+    output: PathBuf,
+}
+
+impl NewDatabase {
+    /// Start a database that will replace `output`, which must not exist unless `force`.
+    pub fn create(output: &Path, force: bool) -> Result<Self> {
+        if output.exists() && !force {
+            return Err(eyre!(
+                "Output database already exists: {}\nUse --force to overwrite it.",
+                output.display()
+            ));
+        }
+        Ok(Self {
+            file: temp_file_for(output)?,
+            output: output.to_path_buf(),
+        })
+    }
+
+    /// Where to open the database while it is being built.
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// Move the finished database into place. Close its connection first.
+    pub fn persist(self) -> Result<()> {
+        self.file
+            .as_file()
+            .sync_all()
+            .wrap_err_with(|| format!("Failed to write: {}", self.output.display()))?;
+        self.file
+            .persist(&self.output)
+            .wrap_err_with(|| format!("Failed to move into place: {}", self.output.display()))?;
+        Ok(())
+    }
+}
+
+/// Write rendered assets under `target_dir/assets/`. Images are named after their
+/// content, so files already present are left alone; mention snapshots under
+/// `assets/<stem>/` are rewritten when their content changed.
+pub fn write_assets(target_dir: &Path, assets: &[Asset]) -> Result<()> {
+    let assets_dir = target_dir.join("assets");
+    for asset in assets {
+        let path = assets_dir.join(&asset.name);
+        let unchanged = if is_content_name(&asset.name) {
+            path.exists()
+        } else {
+            fs::read(&path).is_ok_and(|b| b == asset.data)
+        };
+        if unchanged {
+            continue;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .wrap_err_with(|| format!("Failed to create: {}", dir.display()))?;
+        }
+        write_atomic(&path, &asset.data)
+            .wrap_err_with(|| format!("Failed to write asset: {}", asset.name))?;
+    }
+    Ok(())
+}
+
+/// Delete temp files left in `dir` by a run that was killed mid-write.
+pub fn remove_stale_temp_files(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+            let _ = fs::remove_file(entry.path());
+        }
     }
-    serde_json::from_slice::<Minimal>(json_bytes)
-        .ok()
-        .map(|m| m.updated_at)
 }
 
 /// Decompress data bytes based on the data type.
@@ -105,30 +271,32 @@ pub fn parse_existing_frontmatter(path: &Path) -> Option<FileFrontmatter> {
     }
 
     let mut id: Option<String> = None;
-    let mut updated_at: Option<DateTime<Utc>> = None;
-    let mut include_context = false;
+    let mut content_hash: Option<String> = None;
+    let mut exporter = false;
+    let mut source = ThreadSource::Database;
     let mut bytes_read = 0usize;
 
     for line in lines {
         let line = line.ok()?;
         bytes_read += line.len() + 1;
-        if bytes_read > 2048 || line.trim() == "---" {
+        if bytes_read > 8192 || line.trim() == "---" {
             break;
         }
         if let Some(rest) = line.strip_prefix("id:") {
             id = Some(rest.trim().trim_matches('\'').trim_matches('"').to_string());
-        } else if let Some(rest) = line.strip_prefix("updated_at:") {
-            let val = rest.trim().trim_matches('\'').trim_matches('"');
-            updated_at = DateTime::parse_from_rfc3339(val)
-                .ok()
-                .map(|dt| dt.with_timezone(&Utc));
-        } else if let Some(rest) = line.strip_prefix("include_context:") {
-            include_context = rest.trim() == "true";
+        } else if let Some(rest) = line.strip_prefix("content_hash:") {
+            content_hash = Some(rest.trim().trim_matches('\'').trim_matches('"').to_string());
+        } else if line.trim_end() == "exporter:" {
+            exporter = true;
+        } else if let Some(rest) = line.strip_prefix("source:") {
+            source = serde_yaml::from_str(rest).unwrap_or_default();
         }
     }
-    updated_at.map(|ts| FileFrontmatter {
+    id.as_ref()?;
+    Some(FileFrontmatter {
         id,
-        updated_at: ts,
-        include_context,
+        content_hash,
+        exporter,
+        source,
     })
 }
@@ -0,0 +1,155 @@
+//! The `anonymize` subcommand scrubs content but keeps what deserialization sees.
+
+mod common;
+
+use common::{Fixture, Workspace, count};
+use std::path::PathBuf;
+
+fn anonymized() -> (Workspace, Workspace) {
+    let fixtures: Vec<Fixture> = Fixture::names().iter().map(|n| Fixture::load(n)).collect();
+    let original = Workspace::new(&fixtures);
+    let copy = Workspace::empty();
+    let output = original.run(&[
+        "anonymize".into(),
+        "--db".into(),
+        original.db_path().into_os_string(),
+        "-o".into(),
+        copy.db_path().into_os_string(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    (original, copy)
+}
+
+/// Every row's decompressed JSON, in id order.
+fn threads(db: PathBuf) -> Vec<(String, serde_json::Value)> {
+    let conn = rusqlite::Connection::open(db).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, summary, data_type, data FROM threads ORDER BY id")
+        .unwrap();
+    stmt.query_map([], |row| {
+        let id: String = row.get(0)?;
+        let summary: String = row.get(1)?;
+        let data_type: String = row.get(2)?;
+        let data: Vec<u8> = row.get(3)?;
+        Ok((id, summary, data_type, data))
+    })
+    .unwrap()
+    .map(|row| {
+        let (id, summary, data_type, data) = row.unwrap();
+        let json = match data_type.as_str() {
+            "zstd" => zstd::decode_all(data.as_slice()).unwrap(),
+            _ => data,
+        };
+        let thread: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        (
+            id,
+            serde_json::json!({"summary": summary, "thread": thread}),
+        )
+    })
+    .collect()
+}
+
+#[test]
+fn content_is_replaced_but_structure_is_kept() {
+    let (original, copy) = anonymized();
+    let before = threads(original.db_path());
+    let after = threads(copy.db_path());
+    assert_eq!(before.len(), after.len());
+
+    let text = serde_json::to_string(&after).unwrap();
+    for secret in [
+        "Plain text with",
+        "/src/main.rs",
+        "example.com",
+        "git@github.com",
+        "fn main()",
+        "Content from a newer Zed",
+    ] {
+        assert!(
+            !text.contains(secret),
+            "{:?} survived anonymization",
+            secret
+        );
+    }
+
+    for ((id_before, before), (id_after, after)) in before.iter().zip(&after) {
+        assert_eq!(id_before, id_after);
+        let thread = &after["thread"];
+        assert_eq!(before["thread"]["version"], thread["version"]);
+        assert_eq!(before["thread"]["updated_at"], thread["updated_at"]);
+        assert_eq!(
+            before["summary"].as_str().unwrap().len(),
+            after["summary"].as_str().unwrap().len()
+        );
+    }
+    assert!(text.contains(r#""GitDiff":{"base_ref":"xxxx"}"#));
+    assert!(text.contains(r#""url":"https://xxxxxxx.xxx""#));
+    // Only images get a placeholder PNG; any other `source` is masked as text.
+    assert!(text.contains(r#""Image":{"size":{"height":1,"width":1},"source":"iVBOR"#));
+    assert!(text.contains(r#""Audio":{"source":"XXXX"}"#));
+}
+
+#[test]
+fn anonymized_database_fails_and_succeeds_like_the_original() {
+    let (original, copy) = anonymized();
+    let before = original.export_with_report(&["--include-context"]);
+    let after = copy.export_with_report(&["--include-context"]);
+    assert_eq!(before["outcome"], after["outcome"]);
+    assert_eq!(count(&before, "create"), count(&after, "create"));
+    assert_eq!(before["errors"], after["errors"]);
+
+    let doctor = |ws: &Workspace| {
+        let output = ws.run(&["doctor", "--db", ws.db_path().to_str().unwrap()]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        (output.status.code(), stdout.contains("GitDiff"))
+    };
+    let diagnosis = doctor(&original);
+    assert!(
+        diagnosis.1,
+        "doctor should report the unsupported GitDiff mention"
+    );
+    assert_eq!(diagnosis, doctor(&copy));
+}
+
+#[test]
+fn existing_output_needs_force() {
+    let (original, copy) = anonymized();
+    let args = |force: bool| {
+        let mut args = vec![
+            "anonymize".to_string(),
+            "--db".into(),
+            original.db_path().to_string_lossy().into(),
+            "-o".into(),
+            copy.db_path().to_string_lossy().into(),
+        ];
+        if force {
+            args.push("--force".into());
+        }
+        args
+    };
+    assert!(!original.run(&args(false)).status.success());
+    assert!(original.run(&args(true)).status.success());
+}
+
+#[test]
+fn failed_run_keeps_the_existing_output() {
+    let (original, copy) = anonymized();
+    let before = std::fs::read(copy.db_path()).unwrap();
+    let not_a_db = original.target_dir().with_file_name("empty.db");
+    rusqlite::Connection::open(&not_a_db).unwrap();
+
+    let output = original.run(&[
+        "anonymize".into(),
+        "--db".into(),
+        not_a_db.into_os_string(),
+        "-o".into(),
+        copy.db_path().into_os_string(),
+        "--force".into(),
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read(copy.db_path()).unwrap(), before);
+}
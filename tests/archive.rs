@@ -0,0 +1,125 @@
+//! The lossless raw archive (`--archive-raw`) and `restore` reading it back.
+
+mod common;
+
+use common::{Fixture, Workspace};
+use std::fs;
+use std::path::PathBuf;
+
+fn workspace() -> (Workspace, Fixture) {
+    let fixture = Fixture::load("agent-content");
+    (Workspace::new(std::slice::from_ref(&fixture)), fixture)
+}
+
+fn raw_files(ws: &Workspace) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(ws.target_dir().join("raw"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Run `restore` on the export directory and return its stderr and the restored
+/// thread JSON by id.
+fn restore(ws: &Workspace) -> (String, Vec<(String, Vec<u8>)>) {
+    let restored: PathBuf = ws.db_path().with_file_name("restored.db");
+    let target = ws.target_dir();
+    let output = ws.run(&[
+        "restore",
+        target.to_str().unwrap(),
+        "-o",
+        restored.to_str().unwrap(),
+        "--force",
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(output.status.success(), "{}", stderr);
+
+    let conn = rusqlite::Connection::open(&restored).unwrap();
+    let rows = conn
+        .prepare("SELECT id, data FROM threads ORDER BY id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, Vec<u8>>(1)?)))
+        .unwrap()
+        .map(|row| {
+            let (id, data) = row.unwrap();
+            (id, zstd::decode_all(data.as_slice()).unwrap())
+        })
+        .collect();
+    (stderr, rows)
+}
+
+#[test]
+fn restore_prefers_the_archived_json() {
+    let (ws, fixture) = workspace();
+    ws.export(&["--archive-raw"]);
+    assert_eq!(
+        raw_files(&ws),
+        [format!("{}.json.zst", fixture.id), "index.json".into()]
+    );
+
+    let (stderr, rows) = restore(&ws);
+    assert!(
+        stderr.contains("1 restored (1 from raw archive), 0 skipped"),
+        "{}",
+        stderr
+    );
+    assert_eq!(rows.len(), 1);
+    let json: serde_json::Value = serde_json::from_slice(&rows[0].1).unwrap();
+    assert_eq!(json, fixture.thread);
+}
+
+#[test]
+fn changing_the_format_replaces_the_archived_file() {
+    let (ws, fixture) = workspace();
+    ws.export(&["--archive-raw=json"]);
+    assert_eq!(
+        raw_files(&ws),
+        [format!("{}.json", fixture.id), "index.json".into()]
+    );
+
+    ws.export(&["--archive-raw=zstd"]);
+    assert_eq!(
+        raw_files(&ws),
+        [format!("{}.json.zst", fixture.id), "index.json".into()]
+    );
+}
+
+#[test]
+fn the_indexed_copy_wins_over_a_stale_one() {
+    let (ws, mut fixture) = workspace();
+    ws.export(&["--archive-raw=json"]);
+    let stale = ws.target_dir().join(format!("raw/{}.json", fixture.id));
+    let stale_json = fs::read(&stale).unwrap();
+
+    fixture.retitle("Newer title");
+    ws.upsert(&fixture);
+    ws.export(&["--archive-raw=zstd"]);
+    // A copy left behind by an older version, which did not remove it.
+    fs::write(&stale, &stale_json).unwrap();
+
+    let (_, rows) = restore(&ws);
+    let json: serde_json::Value = serde_json::from_slice(&rows[0].1).unwrap();
+    assert_eq!(json["title"], "Newer title");
+}
+
+#[test]
+fn a_corrupt_archive_file_is_skipped() {
+    let (ws, fixture) = workspace();
+    ws.export(&["--archive-raw"]);
+    fs::write(
+        ws.target_dir().join(format!("raw/{}.json.zst", fixture.id)),
+        b"not zstd",
+    )
+    .unwrap();
+
+    // The thread is rebuilt from its markdown instead.
+    let (stderr, rows) = restore(&ws);
+    assert!(
+        stderr.contains("1 restored (0 from raw archive), 1 skipped"),
+        "{}",
+        stderr
+    );
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].0, fixture.id);
+}
@@ -0,0 +1,262 @@
+//! Images are stored once under `assets/<sha256>.<ext>`, removed once nothing links
+//! to them, and migrated from the per-thread names of older exporters; other files
+//! in `assets/` are left alone. Mention snapshots go under `assets/<stem>/`.
+
+mod common;
+
+use base64::Engine as _;
+use common::{Fixture, Workspace, count};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+const ASSET: &str = "c414cd0e204de974f73753c7e28d7638e7b3691bb8b1a2bab6b25bb7fed7ce77.png";
+
+fn asset_names(ws: &Workspace) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(ws.target_dir().join("assets"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// `user-content` and a copy of it under another id, both with the same image.
+fn two_threads_sharing_an_image() -> (Workspace, Vec<Fixture>) {
+    let original = Fixture::load("user-content");
+    let mut copy = original.clone();
+    copy.id = "0194a0ff-0000-7000-8000-0000000000ff".to_string();
+    copy.retitle("Same screenshot");
+    let fixtures = vec![original, copy];
+    (Workspace::new(&fixtures), fixtures)
+}
+
+#[test]
+fn shared_image_is_stored_once() {
+    let (ws, fixtures) = two_threads_sharing_an_image();
+    ws.export(&[]);
+
+    assert_eq!(asset_names(&ws), [ASSET]);
+    for fixture in &fixtures {
+        let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+        assert!(markdown.contains(&format!("](./assets/{})", ASSET)));
+    }
+}
+
+#[test]
+fn assets_are_removed_once_unreferenced() {
+    let (ws, fixtures) = two_threads_sharing_an_image();
+    ws.export(&[]);
+    fs::write(ws.target_dir().join("assets/stray.png"), b"stray").unwrap();
+
+    ws.delete(&fixtures[0].id);
+    ws.export(&["--prune"]);
+    assert_eq!(
+        asset_names(&ws),
+        [ASSET, "stray.png"],
+        "still linked from the copy"
+    );
+
+    ws.delete(&fixtures[1].id);
+    ws.export(&["--prune"]);
+    assert_eq!(
+        asset_names(&ws),
+        ["stray.png"],
+        "not written by the exporter"
+    );
+}
+
+#[test]
+fn dry_run_removes_nothing() {
+    let (ws, fixtures) = two_threads_sharing_an_image();
+    ws.export(&[]);
+    for fixture in &fixtures {
+        ws.delete(&fixture.id);
+    }
+    ws.export(&["--prune", "--dry-run"]);
+    assert_eq!(asset_names(&ws), [ASSET]);
+}
+
+#[test]
+fn per_thread_asset_names_are_migrated() {
+    let (ws, fixtures) = two_threads_sharing_an_image();
+    ws.export(&[]);
+
+    // Recreate the layout of an older exporter: one copy per thread, named after
+    // the thread, recorded as such in the manifest.
+    let old_names = [
+        "0194a001_user-content-and-mentions.c414cd.png",
+        "0194a0ff_same-screenshot.c414cd.png",
+    ];
+    let assets_dir = ws.target_dir().join("assets");
+    let state_path = ws.target_dir().join(".zed-chat-export/state.json");
+    let mut state: serde_json::Value =
+        serde_json::from_slice(&fs::read(&state_path).unwrap()).unwrap();
+    let image = fs::read(assets_dir.join(ASSET)).unwrap();
+    fs::remove_file(assets_dir.join(ASSET)).unwrap();
+    let markdown: Vec<PathBuf> = fixtures.iter().map(|f| ws.markdown_for(&f.id)).collect();
+    for ((fixture, path), old) in fixtures.iter().zip(&markdown).zip(old_names) {
+        fs::write(assets_dir.join(old), &image).unwrap();
+        let text = fs::read_to_string(path).unwrap().replace(ASSET, old);
+        fs::write(path, &text).unwrap();
+        let entry = &mut state["threads"][&fixture.id];
+        entry["written_hash"] = format!("{:x}", Sha256::digest(&text)).into();
+        entry["assets"] = serde_json::json!([old]);
+    }
+    fs::write(&state_path, serde_json::to_vec(&state).unwrap()).unwrap();
+    // Not in the manifest, so neither renamed nor removed.
+    fs::write(assets_dir.join("diagram.png"), &image).unwrap();
+
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+    assert_eq!(asset_names(&ws), [ASSET, "diagram.png"]);
+    for path in &markdown {
+        let text = fs::read_to_string(path).unwrap();
+        assert!(text.contains(&format!("](./assets/{})", ASSET)));
+        assert!(!text.contains(".c414cd.png"));
+    }
+
+    // The rewritten files are not mistaken for local edits.
+    let report = ws.export_with_report(&["--force"]);
+    assert_eq!(count(&report, "update"), fixtures.len());
+}
+
+/// `user-content` with a single noisy `width`×`height` PNG, which compresses badly.
+fn thread_with_png(width: u32, height: u32) -> Fixture {
+    let img = image::RgbImage::from_fn(width, height, |x, y| {
+        let v = (x * 31 + y * 17) as u8 ^ (x * y) as u8;
+        image::Rgb([v, v.wrapping_mul(3), v.wrapping_mul(7)])
+    });
+    let mut png = Vec::new();
+    img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+
+    let mut fixture = Fixture::load("user-content");
+    fixture.thread["messages"] = serde_json::json!([{"User": {"id": "u1", "content": [
+        {"Image": {
+            "source": base64::engine::general_purpose::STANDARD.encode(&png),
+            "size": {"width": width, "height": height}
+        }}
+    ]}}]);
+    fixture
+}
+
+#[test]
+fn large_images_are_downscaled_and_converted() {
+    let fixture = thread_with_png(64, 32);
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--image-max-dimension", "16", "--image-format", "webp"]);
+
+    let names = asset_names(&ws);
+    assert_eq!(names.len(), 1);
+    assert!(names[0].ends_with(".webp"), "{:?}", names);
+    let stored = image::open(ws.target_dir().join("assets").join(&names[0])).unwrap();
+    assert_eq!((stored.width(), stored.height()), (16, 8));
+
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("![image 64x32](./assets/{})", names[0])));
+    assert!(markdown.contains("image_max_dimension: 16\n"));
+    assert!(markdown.contains("image_format: webp\n"));
+
+    // Without the options the image is re-exported as the original PNG.
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "update"), 1);
+    let names = asset_names(&ws);
+    assert_eq!(names.len(), 1);
+    assert!(names[0].ends_with(".png"), "{:?}", names);
+}
+
+#[test]
+fn images_within_the_limit_are_stored_unchanged() {
+    let fixture = thread_with_png(8, 8);
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--image-max-dimension", "16"]);
+
+    let source = fixture.thread["messages"][0]["User"]["content"][0]["Image"]["source"]
+        .as_str()
+        .unwrap();
+    let original = base64::engine::general_purpose::STANDARD
+        .decode(source)
+        .unwrap();
+    let names = asset_names(&ws);
+    assert_eq!(names, [format!("{:x}.png", Sha256::digest(&original))]);
+}
+
+#[test]
+fn small_images_can_be_inlined() {
+    let fixture = thread_with_png(8, 8);
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--inline-images"]);
+
+    let source = fixture.thread["messages"][0]["User"]["content"][0]["Image"]["source"]
+        .as_str()
+        .unwrap();
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("![image 8x8](data:image/png;base64,{})\n", source)));
+    assert!(markdown.contains("inline_images: 512\n"));
+    assert!(asset_names(&ws).is_empty());
+}
+
+#[test]
+fn images_over_the_inline_cap_stay_assets() {
+    let fixture = thread_with_png(256, 256);
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--inline-images=1"]);
+
+    let names = asset_names(&ws);
+    assert_eq!(names.len(), 1);
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("![image 256x256](./assets/{})", names[0])));
+    assert!(!markdown.contains("data:"));
+}
+
+#[test]
+fn mention_snapshots_are_written_as_assets() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--mention-assets"]);
+
+    let path = ws.markdown_for(&fixture.id);
+    let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let snapshots = ws.target_dir().join("assets").join(&stem);
+    let mut names: Vec<String> = fs::read_dir(&snapshots)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["1-main.rs", "2-docs.md"]);
+    assert_eq!(
+        fs::read_to_string(snapshots.join("1-main.rs")).unwrap(),
+        "fn main() {}"
+    );
+    assert_eq!(
+        fs::read_to_string(snapshots.join("2-docs.md")).unwrap(),
+        "# Docs"
+    );
+
+    let markdown = fs::read_to_string(&path).unwrap();
+    assert!(markdown.contains(&format!("[/src/main.rs](./assets/{}/1-main.rs)\n", stem)));
+    assert!(markdown.contains(&format!(
+        "[https://example.com/docs](./assets/{}/2-docs.md)\n",
+        stem
+    )));
+    assert!(!markdown.contains("fn main() {}"), "not inlined as well");
+}
+
+#[test]
+fn mention_snapshots_follow_a_renamed_thread() {
+    let mut fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--mention-assets"]);
+    let old_stem = ws.markdown_for(&fixture.id).file_stem().unwrap().to_owned();
+
+    fixture.retitle("Renamed");
+    ws.upsert(&fixture);
+    ws.export(&["--mention-assets"]);
+
+    let new_stem = ws.markdown_for(&fixture.id).file_stem().unwrap().to_owned();
+    assert_ne!(old_stem, new_stem);
+    let assets = ws.target_dir().join("assets");
+    assert!(!assets.join(&old_stem).exists());
+    assert!(assets.join(&new_stem).join("1-main.rs").is_file());
+}
@@ -0,0 +1,98 @@
+//! Command-line parsing: subcommands after the global `--db` option, export options
+//! that cannot be combined with a subcommand, and options with an optional value
+//! followed by TARGET_DIR.
+
+mod common;
+
+use common::{Fixture, Workspace};
+
+fn workspace() -> Workspace {
+    Workspace::new(&[Fixture::load("user-content")])
+}
+
+#[test]
+fn db_before_doctor_runs_doctor() {
+    let ws = workspace();
+    let output = ws.run(&["--db", ws.db_path().to_str().unwrap(), "doctor"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("All threads can be exported."), "{}", stdout);
+    assert!(!ws.db_path().with_file_name("doctor").exists());
+}
+
+#[test]
+fn db_before_share_runs_share() {
+    let ws = workspace();
+    let shared = ws.db_path().with_file_name("shared.zed-thread");
+    let output = ws.run(&[
+        "--db",
+        ws.db_path().to_str().unwrap(),
+        "share",
+        "0194a001",
+        "-o",
+        shared.to_str().unwrap(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(shared.is_file());
+}
+
+#[test]
+fn export_options_are_rejected_with_a_subcommand() {
+    let ws = workspace();
+    let db = ws.db_path();
+    for args in [
+        vec!["--db", db.to_str().unwrap(), "--prune", "doctor"],
+        vec!["--db", db.to_str().unwrap(), "out", "doctor"],
+    ] {
+        let output = ws.run(&args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("cannot be used with the 'doctor' subcommand"));
+    }
+}
+
+#[test]
+fn archive_raw_without_a_value_leaves_target_dir_alone() {
+    let ws = workspace();
+    let target = ws.target_dir();
+    let output = ws.run(&[
+        "--db",
+        ws.db_path().to_str().unwrap(),
+        "--quiet",
+        "--archive-raw",
+        target.to_str().unwrap(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(target.join("raw/index.json").is_file());
+}
+
+#[test]
+fn inline_images_without_a_value_leaves_target_dir_alone() {
+    let ws = workspace();
+    let target = ws.target_dir();
+    let output = ws.run(&[
+        "--db",
+        ws.db_path().to_str().unwrap(),
+        "--quiet",
+        "--inline-images",
+        target.to_str().unwrap(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(ws.markdown_files().len(), 1);
+}
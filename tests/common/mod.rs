@@ -0,0 +1,240 @@
+//! Helpers shared by the integration tests: building `threads.db` files from the
+//! JSON fixtures and running the exporter binary against them.
+//!
+//! The pipeline under test is whichever the binary was built with; run the suite
+//! with `--features sequential` as well to cover both.
+
+#![allow(dead_code)]
+
+use rusqlite::{Connection, params};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// The `threads` table as Zed creates it.
+const THREADS_TABLE_SQL: &str = "CREATE TABLE threads (
+    id TEXT PRIMARY KEY,
+    parent_id TEXT,
+    summary TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    data_type TEXT NOT NULL,
+    data BLOB NOT NULL
+)";
+
+/// One row of the `threads` table, as stored in `tests/fixtures/threads/*.json`.
+#[derive(Clone, Deserialize)]
+pub struct Fixture {
+    pub id: String,
+    pub summary: String,
+    pub updated_at: String,
+    /// `"json"` or `"zstd"`, like the `data_type` column.
+    pub data_type: String,
+    pub thread: serde_json::Value,
+}
+
+impl Fixture {
+    pub fn load(name: &str) -> Self {
+        let path = fixtures_dir().join(format!("{}.json", name));
+        let text =
+            fs::read_to_string(&path).unwrap_or_else(|e| panic!("read {}: {}", path.display(), e));
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("parse {}: {}", path.display(), e))
+    }
+
+    /// Names of every fixture, sorted.
+    pub fn names() -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(fixtures_dir())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .map(|p| p.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Change the title in both the `summary` column and the thread JSON.
+    pub fn retitle(&mut self, title: &str) {
+        self.summary = title.to_string();
+        let key = if self.thread.get("title").is_some() {
+            "title"
+        } else {
+            "summary"
+        };
+        self.thread[key] = title.into();
+    }
+}
+
+pub fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/threads")
+}
+
+pub fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+/// A synthetic `threads.db` and an export directory, both removed on drop.
+pub struct Workspace {
+    dir: TempDir,
+}
+
+impl Workspace {
+    /// A workspace whose database has not been created yet.
+    pub fn empty() -> Self {
+        Self {
+            dir: TempDir::new().unwrap(),
+        }
+    }
+
+    pub fn new(fixtures: &[Fixture]) -> Self {
+        let ws = Self::empty();
+        let conn = Connection::open(ws.db_path()).unwrap();
+        conn.execute(THREADS_TABLE_SQL, []).unwrap();
+        for fixture in fixtures {
+            ws.upsert(fixture);
+        }
+        ws
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.path().join("threads.db")
+    }
+
+    pub fn target_dir(&self) -> PathBuf {
+        self.dir.path().join("export")
+    }
+
+    /// Insert or replace a thread, as Zed does when it saves one.
+    pub fn upsert(&self, fixture: &Fixture) {
+        let json = serde_json::to_vec(&fixture.thread).unwrap();
+        let data = match fixture.data_type.as_str() {
+            "zstd" => zstd::encode_all(json.as_slice(), 3).unwrap(),
+            _ => json,
+        };
+        let conn = Connection::open(self.db_path()).unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO threads (id, parent_id, summary, updated_at, data_type, data)
+             VALUES (?, NULL, ?, ?, ?, ?)",
+            params![
+                fixture.id,
+                fixture.summary,
+                fixture.updated_at,
+                fixture.data_type,
+                data
+            ],
+        )
+        .unwrap();
+    }
+
+    pub fn delete(&self, id: &str) {
+        let conn = Connection::open(self.db_path()).unwrap();
+        conn.execute("DELETE FROM threads WHERE id = ?", [id])
+            .unwrap();
+    }
+
+    /// Run an export into `target_dir` with `args` appended, asserting success.
+    pub fn export(&self, args: &[&str]) -> Output {
+        let output = self.run_export(args);
+        assert!(
+            output.status.success(),
+            "export failed ({}):\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    pub fn run_export(&self, args: &[&str]) -> Output {
+        let mut full: Vec<std::ffi::OsString> = vec![
+            "--db".into(),
+            self.db_path().into(),
+            self.target_dir().into(),
+            "--quiet".into(),
+        ];
+        full.extend(args.iter().map(Into::into));
+        self.run(&full)
+    }
+
+    /// Run the binary with `args`, isolated from the user's config file.
+    pub fn run<S: AsRef<std::ffi::OsStr>>(&self, args: &[S]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_zed-chat-export"))
+            .args(args)
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .env("HOME", self.dir.path())
+            .output()
+            .unwrap()
+    }
+
+    /// Export and return the parsed `--report json`.
+    pub fn export_with_report(&self, args: &[&str]) -> serde_json::Value {
+        let mut args = args.to_vec();
+        args.extend(["--report", "json"]);
+        let output = self.export(&args);
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    /// Markdown files in the export directory, sorted by name.
+    pub fn markdown_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(self.target_dir())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "md"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// The exported file of thread `id`, found by its 8-character filename prefix.
+    pub fn markdown_for(&self, id: &str) -> PathBuf {
+        let prefix = &id[..8];
+        let matches: Vec<PathBuf> = self
+            .markdown_files()
+            .into_iter()
+            .filter(|p| p.file_name().unwrap().to_string_lossy().starts_with(prefix))
+            .collect();
+        assert_eq!(matches.len(), 1, "exports of {}: {:?}", id, matches);
+        matches.into_iter().next().unwrap()
+    }
+}
+
+/// The number of threads with `action` in a run report.
+pub fn count(report: &serde_json::Value, action: &str) -> usize {
+    report["threads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|t| t["action"] == action)
+        .count()
+}
+
+/// Compare `actual` with `tests/golden/<name>`, or rewrite the golden file when
+/// `UPDATE_GOLDEN=1` is set. The exporter version in the frontmatter is masked so
+/// releases do not churn the golden files.
+pub fn assert_golden(name: &str, actual: &str) {
+    let actual = actual.replace(
+        &format!("  version: {}\n", env!("CARGO_PKG_VERSION")),
+        "  version: <version>\n",
+    );
+    let path = golden_dir().join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "read {}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            e
+        )
+    });
+    assert!(
+        expected == actual,
+        "{} differs from the golden file (run with UPDATE_GOLDEN=1 to accept):\n--- expected\n{}\n--- actual\n{}",
+        name,
+        expected,
+        actual
+    );
+}
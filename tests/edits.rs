@@ -0,0 +1,80 @@
+//! Local edits to exported files under each `--on-edit` policy, and the user-notes
+//! section carried over every re-export.
+
+mod common;
+
+use common::{Fixture, Workspace, count};
+use std::fs;
+use std::path::PathBuf;
+
+const NOTES: &str =
+    "<!-- zed-chat-export:notes -->\nSee also PR #123.\n<!-- /zed-chat-export:notes -->";
+
+/// An exported thread whose file is then changed by `edit`, after which the thread
+/// gets a new message so the next run renders it again.
+fn edited(edit: impl FnOnce(String) -> String) -> (Workspace, PathBuf) {
+    let mut fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&[]);
+    let path = ws.markdown_for(&fixture.id);
+    fs::write(&path, edit(fs::read_to_string(&path).unwrap())).unwrap();
+
+    fixture.thread["messages"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({"User": {"id": "u9", "content": [{"Text": "One more thing"}]}}));
+    ws.upsert(&fixture);
+    (ws, path)
+}
+
+#[test]
+fn adding_notes_is_not_an_edit() {
+    let (ws, path) = edited(|text| text.replacen("## User", &format!("{}\n## User", NOTES), 1));
+
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "update"), 1, "{:#}", report);
+    let markdown = fs::read_to_string(&path).unwrap();
+    assert!(markdown.contains("One more thing"));
+    assert!(markdown.ends_with(&format!("{}\n", NOTES)));
+
+    // The notes stay out of the hash on later runs too.
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "skip"), 1);
+}
+
+#[test]
+fn conflict_copy_keeps_the_notes() {
+    let (ws, path) = edited(|text| format!("{}\nMy own conclusion.\n{}\n", text, NOTES));
+    let before = fs::read_to_string(&path).unwrap();
+
+    let report = ws.export_with_report(&["--on-edit", "conflict"]);
+    assert_eq!(count(&report, "conflict"), 1, "{:#}", report);
+    assert_eq!(fs::read_to_string(&path).unwrap(), before);
+    let copy = fs::read_to_string(path.with_extension("conflict.md")).unwrap();
+    assert!(copy.contains("One more thing"));
+    assert!(copy.ends_with(&format!("{}\n", NOTES)));
+    assert!(!copy.contains("My own conclusion."));
+}
+
+#[test]
+fn keep_notes_overwrites_everything_but_the_notes() {
+    let (ws, path) = edited(|text| format!("{}\nMy own conclusion.\n{}\n", text, NOTES));
+
+    let report = ws.export_with_report(&["--on-edit", "keep-notes"]);
+    assert_eq!(count(&report, "update"), 1, "{:#}", report);
+    let markdown = fs::read_to_string(&path).unwrap();
+    assert!(markdown.contains("One more thing"));
+    assert!(!markdown.contains("My own conclusion."));
+    assert!(markdown.ends_with(&format!("{}\n", NOTES)));
+    assert!(!path.with_extension("conflict.md").exists());
+}
+
+#[test]
+fn edits_are_skipped_by_default() {
+    let (ws, path) = edited(|text| format!("{}\nMy own conclusion.\n", text));
+    let before = fs::read_to_string(&path).unwrap();
+
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "conflict"), 1, "{:#}", report);
+    assert_eq!(fs::read_to_string(&path).unwrap(), before);
+}
@@ -0,0 +1,101 @@
+//! Threads that fail to export: the exit code of the run, the errors in its report,
+//! and `--fail-fast` / `--max-errors` stopping it before anything is pruned.
+
+mod common;
+
+use common::{Fixture, Workspace, count};
+use std::path::PathBuf;
+
+/// Add a row whose data is not valid zstd, so the thread fails to export.
+fn insert_broken(ws: &Workspace, id: &str) {
+    rusqlite::Connection::open(ws.db_path())
+        .unwrap()
+        .execute(
+            "INSERT INTO threads (id, parent_id, summary, updated_at, data_type, data)
+             VALUES (?, NULL, 'Broken', '2025-01-01T00:00:00Z', 'zstd', x'00')",
+            [id],
+        )
+        .unwrap();
+}
+
+/// Run an export with `--report json`, returning the exit code and the report.
+fn export(ws: &Workspace, args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let mut args = args.to_vec();
+    args.extend(["--report", "json"]);
+    let output = ws.run_export(&args);
+    let report = serde_json::from_slice(&output.stdout).unwrap();
+    (output.status.code(), report)
+}
+
+/// One thread exported and then deleted from the database, so `--prune` has a file
+/// to remove, and `broken` rows that fail.
+fn deleted_and_broken(broken: usize) -> (Workspace, PathBuf) {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&[]);
+    let exported = ws.markdown_for(&fixture.id);
+    ws.delete(&fixture.id);
+    for i in 0..broken {
+        insert_broken(&ws, &format!("0194ffff-0000-7000-8000-00000000000{}", i));
+    }
+    (ws, exported)
+}
+
+#[test]
+fn some_failures_make_a_partial_run() {
+    let ws = Workspace::new(&[Fixture::load("user-content")]);
+    insert_broken(&ws, "0194ffff-0000-7000-8000-000000000000");
+
+    let (code, report) = export(&ws, &[]);
+    assert_eq!(code, Some(3), "{:#}", report);
+    assert_eq!(report["outcome"], "partial");
+    assert_eq!(count(&report, "create"), 1);
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["id"], "0194ffff-0000-7000-8000-000000000000");
+    assert_eq!(errors[0]["kind"], "other");
+}
+
+#[test]
+fn only_failures_make_a_failed_run() {
+    let ws = Workspace::new(&[]);
+    insert_broken(&ws, "0194ffff-0000-7000-8000-000000000000");
+
+    let (code, report) = export(&ws, &[]);
+    assert_eq!(code, Some(4), "{:#}", report);
+    assert_eq!(report["outcome"], "failed");
+}
+
+#[test]
+fn fail_fast_stops_before_pruning() {
+    let (ws, exported) = deleted_and_broken(3);
+
+    let (code, report) = export(&ws, &["--fail-fast", "--prune"]);
+    assert_eq!(code, Some(4), "{:#}", report);
+    assert_eq!(report["aborted"], true);
+    assert_eq!(count(&report, "prune"), 0);
+    assert!(exported.is_file());
+}
+
+#[test]
+fn max_errors_stops_once_reached() {
+    let (ws, exported) = deleted_and_broken(3);
+
+    let (code, report) = export(&ws, &["--max-errors", "2", "--prune"]);
+    assert_eq!(code, Some(4), "{:#}", report);
+    assert_eq!(report["aborted"], true);
+    assert!(report["errors"].as_array().unwrap().len() >= 2);
+    assert!(exported.is_file());
+}
+
+#[test]
+fn errors_below_the_limit_still_prune() {
+    let (ws, exported) = deleted_and_broken(2);
+
+    let (code, report) = export(&ws, &["--max-errors", "3", "--prune"]);
+    assert_eq!(code, Some(3), "{:#}", report);
+    assert!(report.get("aborted").is_none());
+    assert_eq!(report["errors"].as_array().unwrap().len(), 2);
+    assert_eq!(count(&report, "prune"), 1);
+    assert!(!exported.exists());
+}
@@ -0,0 +1,85 @@
+//! The `generate` subcommand writes databases every pipeline can export.
+
+mod common;
+
+use common::{Workspace, count};
+
+fn generate(ws: &Workspace, args: &[&str]) {
+    let db = ws.db_path();
+    let mut full = vec!["generate", "-o", db.to_str().unwrap(), "--force"];
+    full.extend(args);
+    let output = ws.run(&full);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn thread_rows(ws: &Workspace) -> Vec<(String, Option<String>, String, Vec<u8>)> {
+    let conn = rusqlite::Connection::open(ws.db_path()).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, parent_id, data_type, data FROM threads ORDER BY id")
+        .unwrap();
+    stmt.query_map([], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })
+    .unwrap()
+    .collect::<Result<_, _>>()
+    .unwrap()
+}
+
+#[test]
+fn generated_database_exports_without_errors() {
+    let ws = Workspace::empty();
+    generate(
+        &ws,
+        &[
+            "--threads",
+            "8",
+            "--subagents",
+            "3",
+            "--legacy",
+            "4",
+            "--images",
+            "2",
+            "--mentions",
+            "5",
+            "--tool-calls",
+            "3",
+        ],
+    );
+
+    let rows = thread_rows(&ws);
+    assert_eq!(rows.len(), 15);
+    assert_eq!(rows.iter().filter(|r| r.1.is_some()).count(), 3);
+    assert!(rows.iter().any(|r| r.2 == "json"));
+    assert!(rows.iter().any(|r| r.2 == "zstd"));
+
+    let report = ws.export_with_report(&["--include-context"]);
+    assert_eq!(report["outcome"], "success", "{:#}", report);
+    assert_eq!(count(&report, "create"), 15);
+    assert!(ws.target_dir().join("assets").read_dir().unwrap().count() > 0);
+
+    let doctor = ws.run(&["doctor", "--db", ws.db_path().to_str().unwrap()]);
+    assert!(doctor.status.success());
+}
+
+#[test]
+fn same_seed_generates_same_threads() {
+    let a = Workspace::empty();
+    let b = Workspace::empty();
+    generate(&a, &["--threads", "5", "--seed", "42"]);
+    generate(&b, &["--threads", "5", "--seed", "42"]);
+    assert!(thread_rows(&a) == thread_rows(&b));
+
+    generate(&b, &["--threads", "5", "--seed", "43"]);
+    assert!(thread_rows(&a) != thread_rows(&b));
+}
+
+#[test]
+fn data_type_can_be_fixed() {
+    let ws = Workspace::empty();
+    generate(&ws, &["--threads", "4", "--data-type", "json"]);
+    assert!(thread_rows(&ws).iter().all(|r| r.2 == "json"));
+}
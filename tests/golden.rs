@@ -0,0 +1,83 @@
+//! Golden markdown for every fixture in `tests/fixtures/threads`. Regenerate with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+mod common;
+
+use common::{Fixture, Workspace, assert_golden, count};
+use std::fs;
+
+fn export_all(args: &[&str]) -> (Workspace, Vec<(String, Fixture)>) {
+    let fixtures: Vec<(String, Fixture)> = Fixture::names()
+        .into_iter()
+        .map(|name| {
+            let fixture = Fixture::load(&name);
+            (name, fixture)
+        })
+        .collect();
+    let ws = Workspace::new(&fixtures.iter().map(|(_, f)| f.clone()).collect::<Vec<_>>());
+    let report = ws.export_with_report(args);
+    assert_eq!(report["errors"], serde_json::json!([]), "{:#}", report);
+    assert_eq!(count(&report, "create"), fixtures.len());
+    (ws, fixtures)
+}
+
+#[test]
+fn every_fixture_matches_its_golden_markdown() {
+    let (ws, fixtures) = export_all(&["--include-context"]);
+    for (name, fixture) in &fixtures {
+        let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+        assert_golden(&format!("{}.md", name), &markdown);
+    }
+}
+
+#[test]
+fn mentions_are_omitted_without_include_context() {
+    let (ws, _) = export_all(&[]);
+    let markdown =
+        fs::read_to_string(ws.markdown_for("0194a001-0000-7000-8000-000000000001")).unwrap();
+    assert_golden("user-content.no-context.md", &markdown);
+}
+
+#[test]
+fn referenced_assets_are_written() {
+    let (ws, _) = export_all(&[]);
+    let mut referenced = 0;
+    for path in ws.markdown_files() {
+        let markdown = fs::read_to_string(&path).unwrap();
+        for (i, _) in markdown.match_indices("](./assets/") {
+            let rest = &markdown[i + "](./".len()..];
+            let link = &rest[..rest.find(')').unwrap()];
+            assert!(
+                ws.target_dir().join(link).is_file(),
+                "{} links to missing {}",
+                path.display(),
+                link
+            );
+            referenced += 1;
+        }
+    }
+    assert!(
+        referenced > 0,
+        "fixtures should reference at least one asset"
+    );
+}
+
+#[test]
+fn mention_fences_outlast_backticks_in_the_content() {
+    let mut fixture = Fixture::load("user-content");
+    fixture.thread["messages"] = serde_json::json!([{"User": {"id": "u1", "content": [
+        {"Mention": {
+            "uri": {"File": {"abs_path": "/README.md"}},
+            "content": "Build with:\n```sh\ncargo build\n```"
+        }}
+    ]}}]);
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--include-context"]);
+
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(
+        markdown.contains("````md /README.md\nBuild with:\n```sh\ncargo build\n```\n````\n"),
+        "{}",
+        markdown
+    );
+}
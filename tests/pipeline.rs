@@ -0,0 +1,293 @@
+//! End-to-end runs of the export pipeline: fresh, incremental, `--force`, renames
+//! after a title change, re-renders after new render options, `--dry-run`, local
+//! edits without a manifest, file names of threads sharing an id prefix and the mode
+//! of written files.
+
+mod common;
+
+use common::{Fixture, Workspace, count};
+use std::fs;
+
+const USER: &str = "user-content";
+const AGENT: &str = "agent-content";
+const LEGACY: &str = "legacy-v0.2.0";
+
+fn workspace() -> (Workspace, Vec<Fixture>) {
+    let fixtures: Vec<Fixture> = [USER, AGENT, LEGACY].map(Fixture::load).into();
+    (Workspace::new(&fixtures), fixtures)
+}
+
+#[test]
+fn fresh_export_creates_every_thread() {
+    let (ws, fixtures) = workspace();
+    let report = ws.export_with_report(&[]);
+
+    assert_eq!(report["outcome"], "success");
+    assert_eq!(count(&report, "create"), fixtures.len());
+    assert_eq!(ws.markdown_files().len(), fixtures.len());
+    for fixture in &fixtures {
+        let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+        assert!(markdown.contains(&format!("id: {}\n", fixture.id)));
+    }
+    assert!(
+        ws.target_dir()
+            .join(".zed-chat-export/state.json")
+            .is_file()
+    );
+}
+
+#[test]
+fn incremental_export_skips_unchanged_and_updates_changed() {
+    let (ws, mut fixtures) = workspace();
+    ws.export(&[]);
+
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+
+    let changed = &mut fixtures[1];
+    changed.thread["messages"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({"User": {"id": "u9", "content": [{"Text": "One more thing"}]}}));
+    ws.upsert(changed);
+
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "update"), 1);
+    assert_eq!(count(&report, "skip"), fixtures.len() - 1);
+    let markdown = fs::read_to_string(ws.markdown_for(&fixtures[1].id)).unwrap();
+    assert!(markdown.contains("One more thing"));
+}
+
+#[test]
+fn new_threads_are_added_incrementally() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+
+    let added = Fixture::load("legacy-v0.1.0");
+    ws.upsert(&added);
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "create"), 1);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+    ws.markdown_for(&added.id);
+}
+
+#[test]
+fn force_rewrites_unchanged_threads() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+
+    let report = ws.export_with_report(&["--force"]);
+    assert_eq!(count(&report, "update"), fixtures.len());
+    assert_eq!(count(&report, "skip"), 0);
+    assert_eq!(ws.markdown_files().len(), fixtures.len());
+}
+
+#[test]
+fn edits_survive_a_lost_manifest() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+    let path = ws.markdown_for(&fixtures[0].id);
+    let edited = fs::read_to_string(&path).unwrap() + "\nMy own conclusion.\n";
+    fs::write(&path, &edited).unwrap();
+    fs::remove_file(ws.target_dir().join(".zed-chat-export/state.json")).unwrap();
+
+    let report = ws.export_with_report(&["--force"]);
+    assert_eq!(count(&report, "conflict"), 1);
+    assert_eq!(count(&report, "update"), fixtures.len() - 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+}
+
+#[test]
+fn files_adopted_from_disk_are_verified_on_the_next_run() {
+    let (ws, mut fixtures) = workspace();
+    ws.export(&[]);
+    fs::remove_file(ws.target_dir().join(".zed-chat-export/state.json")).unwrap();
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+
+    // The files matched a fresh render, so a changed thread is updated, not
+    // mistaken for a local edit.
+    fixtures[1].thread["messages"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({"User": {"id": "u9", "content": [{"Text": "One more thing"}]}}));
+    ws.upsert(&fixtures[1]);
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "update"), 1);
+}
+
+#[test]
+fn title_change_renames_the_file() {
+    let (ws, mut fixtures) = workspace();
+    ws.export(&[]);
+    let old = ws.markdown_for(&fixtures[0].id);
+
+    fixtures[0].retitle("A better title");
+    ws.upsert(&fixtures[0]);
+    let report = ws.export_with_report(&[]);
+
+    assert_eq!(count(&report, "rename"), 1);
+    let new = ws.markdown_for(&fixtures[0].id);
+    assert!(!old.exists());
+    assert_eq!(
+        new.file_name().unwrap(),
+        "0194a001_a-better-title.md",
+        "renamed to {}",
+        new.display()
+    );
+    let markdown = fs::read_to_string(&new).unwrap();
+    assert!(markdown.contains("title: A better title\n"));
+    assert_eq!(ws.markdown_files().len(), fixtures.len());
+}
+
+#[test]
+fn legacy_title_change_renames_the_file() {
+    let (ws, mut fixtures) = workspace();
+    ws.export(&[]);
+
+    fixtures[2].retitle("Renamed legacy thread");
+    ws.upsert(&fixtures[2]);
+    let report = ws.export_with_report(&[]);
+
+    assert_eq!(count(&report, "rename"), 1);
+    assert_eq!(
+        ws.markdown_for(&fixtures[2].id).file_name().unwrap(),
+        "0194a004_renamed-legacy-thread.md"
+    );
+}
+
+#[test]
+fn changed_render_options_rerender_every_thread() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+
+    let report = ws.export_with_report(&["--tags", "zed"]);
+    assert_eq!(count(&report, "update"), fixtures.len());
+    let markdown = fs::read_to_string(ws.markdown_for(&fixtures[0].id)).unwrap();
+    assert!(markdown.contains("tags:\n- zed\n"), "{}", markdown);
+
+    let report = ws.export_with_report(&["--tags", "zed"]);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+}
+
+#[test]
+fn dry_run_plans_changes_without_making_them() {
+    let (ws, mut fixtures) = workspace();
+    ws.export(&[]);
+    let before: Vec<String> = ws
+        .markdown_files()
+        .iter()
+        .map(|p| fs::read_to_string(p).unwrap())
+        .collect();
+
+    fixtures[1].retitle("Renamed agent thread");
+    ws.upsert(&fixtures[1]);
+    ws.delete(&fixtures[0].id);
+    ws.upsert(&Fixture::load("legacy-v0.1.0"));
+
+    let report = ws.export_with_report(&["--dry-run", "--prune"]);
+    assert_eq!(count(&report, "create"), 1, "{:#}", report);
+    assert_eq!(count(&report, "rename"), 1);
+    assert_eq!(count(&report, "prune"), 1);
+    let after: Vec<String> = ws
+        .markdown_files()
+        .iter()
+        .map(|p| fs::read_to_string(p).unwrap())
+        .collect();
+    assert_eq!(after, before);
+}
+
+#[test]
+fn deleted_threads_are_kept_unless_pruned() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+    ws.delete(&fixtures[0].id);
+
+    ws.export(&[]);
+    assert_eq!(ws.markdown_files().len(), fixtures.len());
+
+    let report = ws.export_with_report(&["--prune"]);
+    assert_eq!(count(&report, "prune"), 1);
+    assert_eq!(ws.markdown_files().len(), fixtures.len() - 1);
+}
+
+#[test]
+fn threads_sharing_an_id_prefix_get_names_in_recency_order() {
+    let mut newer = Fixture::load(USER);
+    newer.id = "abcd1234-1111-4000-8000-000000000000".into();
+    newer.updated_at = "2025-03-01T00:00:00Z".into();
+    let mut older = newer.clone();
+    older.id = "abcd1234-2222-4000-8000-000000000000".into();
+    older.updated_at = "2025-02-01T00:00:00Z".into();
+    let ws = Workspace::new(&[older.clone(), newer.clone()]);
+    ws.export(&[]);
+
+    let names: Vec<String> = ws
+        .markdown_files()
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    let slug = names[0].split_once('_').unwrap().1;
+    assert_eq!(
+        names,
+        [
+            format!("abcd1234-222_{}", slug),
+            format!("abcd1234_{}", slug)
+        ],
+        "the newest thread takes the short prefix"
+    );
+
+    // The names hold on later runs.
+    let report = ws.export_with_report(&[]);
+    assert_eq!(count(&report, "skip"), 2);
+}
+
+#[test]
+fn prune_leaves_files_the_exporter_did_not_write() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+    let note = ws.target_dir().join("notes.md");
+    fs::write(&note, "---\nid: my-note-1\n---\n\nMy own notes.\n").unwrap();
+
+    let report = ws.export_with_report(&["--prune"]);
+    assert_eq!(count(&report, "prune"), 0);
+    assert_eq!(count(&report, "skip"), fixtures.len());
+    assert!(note.exists());
+}
+
+#[test]
+fn prune_keeps_files_whose_written_hash_is_unknown() {
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+    let path = ws.markdown_for(&fixtures[0].id);
+    ws.delete(&fixtures[0].id);
+    fs::remove_file(ws.target_dir().join(".zed-chat-export/state.json")).unwrap();
+
+    let report = ws.export_with_report(&["--prune"]);
+    assert_eq!(count(&report, "prune"), 0);
+    assert_eq!(count(&report, "conflict"), 1);
+    assert!(path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn exported_files_get_the_usual_mode_and_keep_a_changed_one() {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let (ws, fixtures) = workspace();
+    ws.export(&[]);
+    // What any new file gets under the current umask.
+    let reference = ws.target_dir().join("reference");
+    fs::write(&reference, "").unwrap();
+    let path = ws.markdown_for(&fixtures[0].id);
+    assert_eq!(mode(&path), mode(&reference));
+    assert_eq!(
+        mode(&ws.target_dir().join(".zed-chat-export/state.json")),
+        mode(&reference)
+    );
+
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    ws.export(&["--force"]);
+    assert_eq!(mode(&path), 0o640);
+}
@@ -0,0 +1,104 @@
+//! `restore` rebuilding a threads.db from markdown exports.
+
+mod common;
+
+use common::{Fixture, Workspace};
+use std::fs;
+use std::path::Path;
+
+const NOTES: &str =
+    "<!-- zed-chat-export:notes -->\nSee also PR #123.\n<!-- /zed-chat-export:notes -->";
+
+/// Run `restore` from `source` into `ws`'s database path.
+fn restore(ws: &Workspace, source: &Path, args: &[&str]) -> std::process::Output {
+    let db = ws.db_path();
+    let mut full = vec![
+        "restore",
+        source.to_str().unwrap(),
+        "-o",
+        db.to_str().unwrap(),
+    ];
+    full.extend(args);
+    ws.run(&full)
+}
+
+/// Markdown after the frontmatter, which differs in `content_hash`.
+fn body(path: &Path) -> String {
+    let text = fs::read_to_string(path).unwrap();
+    text.split_once("\n---\n").unwrap().1.to_string()
+}
+
+#[test]
+fn restored_threads_export_to_the_same_markdown() {
+    let fixtures: Vec<Fixture> = ["user-content", "agent-content"].map(Fixture::load).into();
+    let original = Workspace::new(&fixtures);
+    original.export(&[]);
+
+    let restored = Workspace::empty();
+    let output = restore(&restored, &original.target_dir(), &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    restored.export(&[]);
+
+    for fixture in &fixtures {
+        assert_eq!(
+            body(&restored.markdown_for(&fixture.id)),
+            body(&original.markdown_for(&fixture.id)),
+            "{}",
+            fixture.id
+        );
+    }
+}
+
+#[test]
+fn notes_and_conflict_copies_are_left_out() {
+    let fixture = Fixture::load("user-content");
+    let original = Workspace::new(std::slice::from_ref(&fixture));
+    original.export(&[]);
+    let path = original.markdown_for(&fixture.id);
+    let exported = body(&path);
+    fs::write(
+        &path,
+        format!("{}\n{}\n", fs::read_to_string(&path).unwrap(), NOTES),
+    )
+    .unwrap();
+    fs::write(path.with_extension("conflict.md"), "not an export").unwrap();
+
+    let restored = Workspace::empty();
+    let output = restore(&restored, &original.target_dir(), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1 restored (0 from raw archive), 0 skipped"),
+        "{}",
+        stderr
+    );
+    restored.export(&[]);
+    assert_eq!(body(&restored.markdown_for(&fixture.id)), exported);
+}
+
+#[test]
+fn existing_database_needs_force_and_survives_a_failure() {
+    let original = Workspace::new(&[Fixture::load("user-content")]);
+    original.export(&[]);
+    let restored = Workspace::empty();
+    fs::write(restored.db_path(), b"precious").unwrap();
+
+    assert!(
+        !restore(&restored, &original.target_dir(), &[])
+            .status
+            .success()
+    );
+    let missing = original.target_dir().join("missing");
+    assert!(!restore(&restored, &missing, &["--force"]).status.success());
+    assert_eq!(fs::read(restored.db_path()).unwrap(), b"precious");
+
+    assert!(
+        restore(&restored, &original.target_dir(), &["--force"])
+            .status
+            .success()
+    );
+    assert_ne!(fs::read(restored.db_path()).unwrap(), b"precious");
+}
@@ -0,0 +1,246 @@
+//! Zed's rules library is exported once to `rules/<id>.md`, where rule mentions link
+//! once their rule is there.
+
+mod common;
+
+use common::{Fixture, Workspace};
+use heed::types::Bytes;
+use heed::{Database, EnvOpenOptions};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The rule mentioned by the `user-content` fixture.
+const STYLE_GUIDE: &str = "7c9e6679-7425-40de-944b-e07fc1f90ae7";
+
+/// `(uuid, title, saved_at, body)` of a user rule.
+type UserRule<'a> = (&'a str, &'a str, &'a str, &'a str);
+
+/// Write a prompt store laid out like Zed's, with `rules` and one built-in prompt
+/// override, which is not exported.
+fn write_library(path: &Path, rules: &[UserRule]) {
+    fs::create_dir_all(path).unwrap();
+    let env = unsafe { EnvOpenOptions::new().max_dbs(2).open(path) }.unwrap();
+    let mut txn = env.write_txn().unwrap();
+    let metadata: Database<Bytes, Bytes> =
+        env.create_database(&mut txn, Some("metadata.v2")).unwrap();
+    let bodies: Database<Bytes, Bytes> = env.create_database(&mut txn, Some("bodies.v2")).unwrap();
+    let mut put = |id: serde_json::Value, title: &str, saved_at: &str, body: &str| {
+        let key = serde_json::to_vec(&id).unwrap();
+        let meta = serde_json::json!({
+            "id": id, "title": title, "default": false, "saved_at": saved_at
+        });
+        let meta = serde_json::to_vec(&meta).unwrap();
+        metadata.put(&mut txn, &key, &meta).unwrap();
+        bodies.put(&mut txn, &key, body.as_bytes()).unwrap();
+    };
+    for (uuid, title, saved_at, body) in rules {
+        put(
+            serde_json::json!({"kind": "User", "uuid": uuid}),
+            title,
+            saved_at,
+            body,
+        );
+    }
+    put(
+        serde_json::json!({"kind": "CommitMessage"}),
+        "Commit message",
+        "2025-01-01T00:00:00Z",
+        "Write a commit message.",
+    );
+    txn.commit().unwrap();
+}
+
+fn library_path(ws: &Workspace) -> PathBuf {
+    ws.db_path().with_file_name("prompts-library-db.0.mdb")
+}
+
+fn rule_file(ws: &Workspace, id: &str) -> PathBuf {
+    ws.target_dir().join(format!("rules/{}.md", id))
+}
+
+fn rule_files(ws: &Workspace) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(ws.target_dir().join("rules"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn rules_are_exported_where_mentions_link() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    let library = library_path(&ws);
+    write_library(
+        &library,
+        &[(
+            STYLE_GUIDE,
+            "Style guide",
+            "2025-01-02T00:00:00Z",
+            "Use tabs.",
+        )],
+    );
+    ws.export(&["--include-context", "--rules-db", library.to_str().unwrap()]);
+
+    assert_eq!(rule_files(&ws), [format!("{}.md", STYLE_GUIDE)]);
+    let rule = fs::read_to_string(rule_file(&ws, STYLE_GUIDE)).unwrap();
+    assert_eq!(
+        rule,
+        format!(
+            "---\ntitle: Style guide\nid: {}\ndefault: false\nsaved_at: 2025-01-02T00:00:00Z\n---\n\nUse tabs.\n",
+            STYLE_GUIDE
+        )
+    );
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("[Style guide](./rules/{}.md)", STYLE_GUIDE)));
+}
+
+#[test]
+fn mentions_link_the_rule_they_name_when_titles_collide() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    let library = library_path(&ws);
+    let newer = "22222222-0000-4000-8000-000000000000";
+    write_library(
+        &library,
+        &[
+            (
+                STYLE_GUIDE,
+                "Style guide",
+                "2025-01-01T00:00:00Z",
+                "Use tabs.",
+            ),
+            (newer, "Style guide", "2025-02-01T00:00:00Z", "Use spaces."),
+        ],
+    );
+    ws.export(&["--include-context", "--rules-db", library.to_str().unwrap()]);
+
+    assert_eq!(
+        rule_files(&ws),
+        [format!("{}.md", newer), format!("{}.md", STYLE_GUIDE)]
+    );
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    let link = format!("](./rules/{}.md)", STYLE_GUIDE);
+    assert!(markdown.contains(&link), "{}", markdown);
+    let linked = fs::read_to_string(rule_file(&ws, STYLE_GUIDE)).unwrap();
+    assert!(linked.ends_with("\nUse tabs.\n"));
+}
+
+#[test]
+fn renamed_rules_keep_their_mention_links() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    let library = library_path(&ws);
+    write_library(
+        &library,
+        &[(
+            STYLE_GUIDE,
+            "Indentation",
+            "2025-01-02T00:00:00Z",
+            "Use tabs.",
+        )],
+    );
+    ws.export(&["--include-context", "--rules-db", library.to_str().unwrap()]);
+
+    // The mention still carries the old name; the link follows the id.
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("[Style guide](./rules/{}.md)", STYLE_GUIDE)));
+    assert!(rule_file(&ws, STYLE_GUIDE).is_file());
+}
+
+#[test]
+fn mentions_are_plain_text_until_the_rule_is_exported() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&["--include-context"]);
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains("Rule *Style guide*:\n"), "{}", markdown);
+    assert!(!markdown.contains("](./rules/"));
+
+    let library = library_path(&ws);
+    write_library(
+        &library,
+        &[(
+            STYLE_GUIDE,
+            "Style guide",
+            "2025-01-02T00:00:00Z",
+            "Use tabs.",
+        )],
+    );
+    ws.export(&["--include-context", "--rules-db", library.to_str().unwrap()]);
+    let markdown = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+    assert!(markdown.contains(&format!("[Style guide](./rules/{}.md)", STYLE_GUIDE)));
+}
+
+#[test]
+fn removed_rules_are_pruned_and_other_files_kept() {
+    let ws = Workspace::new(&[]);
+    let removed = "22222222-0000-4000-8000-000000000000";
+    let style_guide = (
+        STYLE_GUIDE,
+        "Style guide",
+        "2025-01-02T00:00:00Z",
+        "Use tabs.",
+    );
+    let before = library_path(&ws);
+    write_library(
+        &before,
+        &[
+            style_guide,
+            (removed, "Old rule", "2025-01-01T00:00:00Z", "Be terse."),
+        ],
+    );
+    ws.export(&["--rules-db", before.to_str().unwrap()]);
+    fs::write(
+        ws.target_dir().join("rules/my-rule.md"),
+        "---
+id: mine
+---
+",
+    )
+    .unwrap();
+
+    let after = ws.db_path().with_file_name("after.mdb");
+    write_library(&after, &[style_guide]);
+    let args = ["--rules-db", after.to_str().unwrap()];
+    ws.export(&args);
+    let kept = format!("{}.md", STYLE_GUIDE);
+    let gone = format!("{}.md", removed);
+    assert_eq!(rule_files(&ws), [gone.as_str(), &kept, "my-rule.md"]);
+
+    ws.export(&[&args[..], &["--prune"]].concat());
+    assert_eq!(rule_files(&ws), [kept.as_str(), "my-rule.md"]);
+}
+
+#[test]
+fn missing_library_is_an_error_only_when_given() {
+    let ws = Workspace::new(&[]);
+    ws.export(&[]);
+    assert!(!ws.target_dir().join("rules").exists());
+
+    let output = ws.run_export(&["--rules-db", library_path(&ws).to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Rules library not found"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn library_is_found_in_zeds_data_directory() {
+    let ws = Workspace::new(&[]);
+    // `run` points HOME at the workspace.
+    let library = ws
+        .db_path()
+        .with_file_name(".local/share/zed/prompts/prompts-library-db.0.mdb");
+    write_library(
+        &library,
+        &[(
+            STYLE_GUIDE,
+            "Style guide",
+            "2025-01-02T00:00:00Z",
+            "Use tabs.",
+        )],
+    );
+    ws.export(&[]);
+    assert!(rule_file(&ws, STYLE_GUIDE).is_file());
+}
@@ -0,0 +1,71 @@
+//! Schema selection: threads of a version missing from the registry, `--zed-schema`
+//! for export and `doctor` alike, and the table column check.
+
+mod common;
+
+use common::{Fixture, Workspace};
+
+/// A legacy thread whose JSON claims a version no schema is registered for.
+fn unregistered_version() -> Workspace {
+    let mut fixture = Fixture::load("legacy-v0.2.0");
+    fixture.thread["version"] = "0.9.0".into();
+    Workspace::new(&[fixture])
+}
+
+fn doctor(ws: &Workspace, args: &[&str]) -> (Option<i32>, String) {
+    let db = ws.db_path();
+    let mut full = vec!["doctor", "--db", db.to_str().unwrap()];
+    full.extend(args);
+    let output = ws.run(&full);
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+    )
+}
+
+#[test]
+fn unregistered_version_is_tried_against_every_schema() {
+    let ws = unregistered_version();
+    let report = ws.export_with_report(&[]);
+    assert_eq!(report["outcome"], "success", "{:#}", report);
+
+    let (code, stdout) = doctor(&ws, &[]);
+    assert_eq!(code, Some(0), "{}", stdout);
+    assert!(stdout.contains("0.9.0: 1 (unknown)"), "{}", stdout);
+}
+
+#[test]
+fn zed_schema_applies_to_export_and_doctor() {
+    let ws = unregistered_version();
+    let output = ws.run_export(&["--zed-schema", "agent"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(ws.markdown_files().is_empty());
+
+    let (code, stdout) = doctor(&ws, &["--zed-schema", "agent"]);
+    assert_eq!(code, Some(5), "{}", stdout);
+    assert!(
+        stdout.contains("0.9.0: 1 (unknown, read as agent)"),
+        "{}",
+        stdout
+    );
+
+    let report = ws.export_with_report(&["--zed-schema", "legacy"]);
+    assert_eq!(report["outcome"], "success", "{:#}", report);
+}
+
+#[test]
+fn missing_column_stops_export_and_doctor() {
+    let ws = Workspace::new(&[Fixture::load("user-content")]);
+    rusqlite::Connection::open(ws.db_path())
+        .unwrap()
+        .execute("ALTER TABLE threads DROP COLUMN summary", [])
+        .unwrap();
+
+    let output = ws.run_export(&[]);
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no summary column"), "{}", stderr);
+
+    let (code, stdout) = doctor(&ws, &[]);
+    assert_eq!(code, Some(5), "{}", stdout);
+}
@@ -0,0 +1,71 @@
+//! `share` writing a SharedThread file and `render-shared` rendering one into the
+//! export directory.
+
+mod common;
+
+use common::{Fixture, Workspace, count};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn share(ws: &Workspace, id: &str) -> PathBuf {
+    let shared = ws.db_path().with_file_name("shared.zed-thread");
+    let output = ws.run(&[
+        "share",
+        &id[..8],
+        "--db",
+        ws.db_path().to_str().unwrap(),
+        "-o",
+        shared.to_str().unwrap(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    shared
+}
+
+fn render_shared(ws: &Workspace, shared: &Path) -> PathBuf {
+    let target = ws.target_dir();
+    let output = ws.run(&[
+        "render-shared",
+        shared.to_str().unwrap(),
+        target.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    PathBuf::from(stderr.trim().strip_prefix("Wrote ").unwrap())
+}
+
+#[test]
+fn shared_thread_renders_like_the_export() {
+    let fixture = Fixture::load("agent-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    ws.export(&[]);
+    let exported = fs::read_to_string(ws.markdown_for(&fixture.id)).unwrap();
+
+    let rendered = render_shared(&ws, &share(&ws, &fixture.id));
+    let markdown = fs::read_to_string(&rendered).unwrap();
+    assert!(markdown.contains("source: shared"), "{}", markdown);
+    let body = |text: &str| text.split_once("\n---\n").unwrap().1.to_string();
+    assert_eq!(body(&markdown), body(&exported));
+
+    // The same file renders to the same markdown file.
+    assert_eq!(render_shared(&ws, &share(&ws, &fixture.id)), rendered);
+    assert_eq!(ws.markdown_files().len(), 2);
+}
+
+#[test]
+fn prune_keeps_shared_threads_without_a_warning() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    let rendered = render_shared(&ws, &share(&ws, &fixture.id));
+
+    let output = ws.export(&["--prune", "--report", "json"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("edited locally"), "{}", stderr);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(count(&report, "conflict"), 0, "{:#}", report);
+    assert_eq!(count(&report, "prune"), 0, "{:#}", report);
+    assert!(rendered.is_file());
+}
@@ -0,0 +1,149 @@
+//! Text threads (`conversations/*.zed.json`) are exported next to the database's
+//! threads, in the same format. Golden files are regenerated with `UPDATE_GOLDEN=1`.
+
+mod common;
+
+use common::{Fixture, Workspace, assert_golden, count};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Copy `tests/fixtures/text-threads` into the workspace with a fixed modification
+/// time, which becomes the threads' `updated_at`.
+fn conversations_dir(ws: &Workspace) -> PathBuf {
+    let dir = ws.db_path().with_file_name("conversations");
+    fs::create_dir_all(&dir).unwrap();
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/text-threads");
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        let copy = dir.join(path.file_name().unwrap());
+        fs::copy(&path, &copy).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_735_689_600);
+        fs::File::options()
+            .write(true)
+            .open(&copy)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+    dir
+}
+
+#[test]
+fn text_threads_match_their_golden_markdown() {
+    let ws = Workspace::new(&[]);
+    let dir = conversations_dir(&ws);
+    let report = ws.export_with_report(&["--text-threads", dir.to_str().unwrap()]);
+    assert_eq!(count(&report, "create"), 2, "{:#}", report);
+
+    let current = fs::read_to_string(ws.markdown_for("5b1f6d2e-8c3a-4f7e-9d10-2a4b6c8e0f12"));
+    assert_golden("text-thread.md", &current.unwrap());
+
+    // Version 0.1.0 has no id or summary: both come from the file name.
+    let files = ws.markdown_files();
+    let old = files
+        .iter()
+        .find(|p| p.to_string_lossy().ends_with("_lifetimes.md"))
+        .expect("0.1.0 thread exported under its file name");
+    let markdown = fs::read_to_string(old).unwrap();
+    assert!(markdown.contains("title: Lifetimes\n"));
+    assert!(markdown.contains(
+        "## User\n\nWhat is a lifetime?\n\n## Assistant\n\nA scope for which a reference is valid.\n"
+    ));
+}
+
+#[test]
+fn unchanged_text_threads_are_skipped_and_deleted_ones_pruned() {
+    let ws = Workspace::new(&[]);
+    let dir = conversations_dir(&ws);
+    let args = ["--text-threads", dir.to_str().unwrap()];
+    ws.export(&args);
+
+    let report = ws.export_with_report(&args);
+    assert_eq!(count(&report, "skip"), 2);
+    let derived_id = report["threads"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["id"].as_str().unwrap())
+        .find(|id| !id.starts_with("5b1f6d2e"))
+        .unwrap()
+        .to_string();
+
+    fs::remove_file(dir.join("Lifetimes - 2.zed.json")).unwrap();
+    let report = ws.export_with_report(&[&args[..], &["--prune"]].concat());
+    assert_eq!(count(&report, "prune"), 1);
+    assert_eq!(count(&report, "skip"), 1);
+    assert_eq!(ws.markdown_files().len(), 1);
+    assert!(
+        !ws.markdown_files()[0]
+            .to_string_lossy()
+            .contains(&derived_id[..8])
+    );
+}
+
+#[test]
+fn text_threads_are_kept_when_their_directory_is_not_read() {
+    let ws = Workspace::new(&[]);
+    let dir = conversations_dir(&ws);
+    ws.export(&["--text-threads", dir.to_str().unwrap()]);
+
+    let report = ws.export_with_report(&["--prune"]);
+    assert_eq!(count(&report, "prune"), 0);
+    assert_eq!(ws.markdown_files().len(), 2);
+}
+
+#[test]
+fn restore_leaves_text_threads_out() {
+    let fixture = Fixture::load("user-content");
+    let ws = Workspace::new(std::slice::from_ref(&fixture));
+    let dir = conversations_dir(&ws);
+    ws.export(&["--text-threads", dir.to_str().unwrap()]);
+    let markdown = fs::read_to_string(ws.markdown_for("5b1f6d2e-8c3a-4f7e-9d10-2a4b6c8e0f12"));
+    assert!(markdown.unwrap().contains("source: text_thread\n"));
+
+    let restored = ws.db_path().with_file_name("restored.db");
+    let target = ws.target_dir();
+    let output = ws.run(&[
+        "restore",
+        target.to_str().unwrap(),
+        "-o",
+        restored.to_str().unwrap(),
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Skipped"));
+
+    let conn = rusqlite::Connection::open(&restored).unwrap();
+    let ids: Vec<String> = conn
+        .prepare("SELECT id FROM threads")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(ids, [fixture.id]);
+}
+
+#[test]
+fn unreadable_text_thread_is_reported() {
+    let ws = Workspace::new(&[]);
+    let dir = conversations_dir(&ws);
+    fs::write(dir.join("Broken - 1.zed.json"), b"{\"zed\": \"context\"}").unwrap();
+    let output = ws.run_export(&["--text-threads", dir.to_str().unwrap(), "--report", "json"]);
+    assert_eq!(output.status.code(), Some(3), "partial failure");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(count(&report, "create"), 2);
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert!(
+        errors[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("text thread")
+    );
+}